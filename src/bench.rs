@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+use macroquad::prelude::*;
+
+use crate::common::pixels;
+use crate::noise::{Noise, NoiseSettings};
+use crate::planet::Planet;
+use crate::world::SURFACE_RESOLUTION;
+
+/// Radius of the home planet, the largest one the game renders.
+const RADIUS: f32 = 1500.0;
+/// How many times each rasterizer runs, the fastest run is reported.
+const RUNS: u32 = 3;
+/// How long rasterizing the planet may take, it is done again after every terrain edit.
+const TARGET: Duration = Duration::from_millis(100);
+
+/// Times the planet rasterizers against each other and checks they agree on every pixel.
+///
+/// # Panics
+///
+/// If the default noise settings stop being valid.
+pub fn rasterization(seed: Option<u64>) {
+    let seed = seed.unwrap_or(0);
+    let noise =
+        Noise::new(seed, NoiseSettings::default()).expect("default noise settings are valid");
+    let mut planet = Planet::new(vec2(0.0, 0.0), RADIUS, SURFACE_RESOLUTION, &noise);
+    let threads = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+    println!("seed {seed}, radius {RADIUS}, {threads} threads");
+    compare(&planet, "surface");
+    planet.carve_caves(&noise);
+    compare(&planet, "caves");
+}
+
+fn compare(planet: &Planet, label: &str) {
+    let Rect { x, y, w, h } = planet.image_extents();
+    let (Some(width), Some(height)) = (pixels(w), pixels(h)) else {
+        println!("{label}: the planet is too big for an image");
+        return;
+    };
+    let origin = vec2(x, y);
+    let (fast, fast_time) = fastest(|| Planet::as_image_region(planet, origin, width, height));
+    let (reference, reference_time) =
+        fastest(|| Planet::as_image_region_per_pixel(planet, origin, width, height));
+    let mismatches = fast
+        .bytes
+        .chunks_exact(4)
+        .zip(reference.bytes.chunks_exact(4))
+        .filter(|&(fast, reference)| fast != reference)
+        .count();
+    let verdict = if fast_time <= TARGET {
+        "within"
+    } else {
+        "over"
+    };
+    println!(
+        "{label}: {width}x{height}, per pixel {:.0}ms, scanline {:.0}ms ({:.1}x faster, {verdict} the {}ms target), {mismatches} mismatched pixels",
+        reference_time.as_secs_f64() * 1000.0,
+        fast_time.as_secs_f64() * 1000.0,
+        reference_time.as_secs_f64() / fast_time.as_secs_f64(),
+        TARGET.as_millis(),
+    );
+}
+
+fn fastest(rasterize: impl Fn() -> Image) -> (Image, Duration) {
+    let mut best: Option<(Image, Duration)> = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let image = rasterize();
+        let time = start.elapsed();
+        if best.as_ref().is_none_or(|&(_, best)| time < best) {
+            best = Some((image, time));
+        }
+    }
+    best.expect("the rasterizers run at least once")
+}
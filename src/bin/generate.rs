@@ -0,0 +1,16 @@
+//! Renders a planet to a file without opening a window. A binary of its own, so it doesn't
+//! need the windowing and GL libraries the game links.
+use planet_shaper::CliError;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match planet_shaper::generate(&args) {
+        Ok(()) => {}
+        Err(CliError::Help) => println!("{}", CliError::Help),
+        Err(error) => {
+            eprintln!("{error}");
+            eprintln!("run with --help to see the options");
+            std::process::exit(2);
+        }
+    }
+}
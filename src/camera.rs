@@ -1,10 +1,46 @@
 use std::default::Default;
+use std::f32::consts::{PI, TAU};
 
-use macroquad::input::{is_key_down, mouse_position, KeyCode};
+use macroquad::camera::Camera2D;
+use macroquad::input::{mouse_position, mouse_wheel};
 use macroquad::math::{vec2, Rect, Vec2};
 use macroquad::window::{screen_height, screen_width};
 
-use crate::common::map;
+use crate::common::{angle_difference, rotate};
+use crate::controls::{Action, InputMap};
+
+/// Narrowest and widest the view can get, in world units across.
+const MIN_VIEW_WIDTH: f32 = 50.0;
+const MAX_VIEW_WIDTH: f32 = 100_000.0;
+/// How fast the pan keys move the view, in view widths per second.
+const PAN_SPEED: f32 = 0.3;
+/// How much holding a zoom key zooms in over a second.
+const ZOOM_SPEED: f32 = 3.0;
+/// How much one notch of the mouse wheel zooms in.
+const WHEEL_ZOOM: f32 = 1.1;
+/// Room left around something the camera is fit to, as a fraction of the view.
+const FIT_MARGIN: f32 = 0.1;
+
+/// How the camera chases what it follows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FollowSettings {
+    /// How quickly the camera catches up, per second. Higher is snappier.
+    pub stiffness: f32,
+    /// Like `stiffness`, for turning to the followed rotation.
+    pub rotation_stiffness: f32,
+    /// Seconds of the followed velocity the camera looks ahead by.
+    pub look_ahead: f32,
+}
+
+impl Default for FollowSettings {
+    fn default() -> Self {
+        Self {
+            stiffness: 8.0,
+            rotation_stiffness: 5.0,
+            look_ahead: 0.25,
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Camera {
@@ -13,6 +49,13 @@ pub struct Camera {
     pub zoom: Vec2,
     pub followed_pos: Option<Vec2>,
     pub followed_rot: Option<f32>,
+    pub followed_velocity: Vec2,
+    pub follow: FollowSettings,
+    /// Speeds of the springs pulling `target` and `rotation` along.
+    velocity: Vec2,
+    angular_velocity: f32,
+    /// The world point held under the cursor while dragging the view.
+    drag_anchor: Option<Vec2>,
 }
 
 impl Camera {
@@ -28,26 +71,107 @@ impl Camera {
             ),
             followed_pos: None,
             followed_rot: None,
+            followed_velocity: vec2(0.0, 0.0),
+            follow: FollowSettings::default(),
+            velocity: vec2(0.0, 0.0),
+            angular_velocity: 0.0,
+            drag_anchor: None,
         }
     }
 
-    pub fn update(&mut self) {
-        if let Some(target) = self.followed_pos {
-            self.target = target;
+    /// Moves the camera `dt` seconds closer to what it follows.
+    pub fn update(&mut self, dt: f32) {
+        let FollowSettings {
+            stiffness,
+            rotation_stiffness,
+            look_ahead,
+        } = self.follow;
+        if let Some(position) = self.followed_pos {
+            let goal = position + self.followed_velocity * look_ahead;
+            self.target = vec2(
+                damp(self.target.x, goal.x, &mut self.velocity.x, stiffness, dt),
+                damp(self.target.y, goal.y, &mut self.velocity.y, stiffness, dt),
+            );
         }
-        if let Some(rot) = self.followed_rot {
-            self.rotation = rot;
+        if let Some(rotation) = self.followed_rot {
+            // Turn the short way round, the followed rotation may wrap past a full turn.
+            let goal = self.rotation + angle_difference(self.rotation, rotation);
+            let rotation = damp(
+                self.rotation,
+                goal,
+                &mut self.angular_velocity,
+                rotation_stiffness,
+                dt,
+            );
+            self.rotation = (rotation + PI).rem_euclid(TAU) - PI;
         }
     }
 
+    /// Stops following, the camera stays where it is.
     pub fn unfollow(&mut self) {
         self.followed_pos = None;
         self.followed_rot = None;
+        self.followed_velocity = vec2(0.0, 0.0);
+        self.velocity = vec2(0.0, 0.0);
+        self.angular_velocity = 0.0;
     }
 
-    pub fn set_follow(&mut self, position: Option<Vec2>, _rotation: Option<f32>) {
+    /// Eases the camera towards `position` and `rotation`, looking ahead along `velocity`.
+    pub const fn set_follow(
+        &mut self,
+        position: Option<Vec2>,
+        rotation: Option<f32>,
+        velocity: Vec2,
+    ) {
         self.followed_pos = position;
-        //self.followed_rot = rotation;
+        self.followed_rot = rotation;
+        self.followed_velocity = velocity;
+    }
+
+    /// Scales the zoom by `factor`, within the narrowest and widest view, keeping the aspect ratio.
+    pub fn scale_zoom(&mut self, factor: f32) {
+        let zoom = (self.zoom.x * factor).clamp(2.0 / MAX_VIEW_WIDTH, 2.0 / MIN_VIEW_WIDTH);
+        self.zoom *= zoom / self.zoom.x;
+    }
+
+    /// Scales the zoom by `factor`, keeping the world under `point` on a screen
+    /// of `screen_size` where it is.
+    pub fn zoom_towards(&mut self, factor: f32, point: Vec2, screen_size: Vec2) {
+        let before = self.screen_to_world(point, screen_size);
+        self.scale_zoom(factor);
+        self.target += before - self.screen_to_world(point, screen_size);
+    }
+
+    /// Pans so the world point under the cursor when the drag started stays under `cursor`,
+    /// `None` ends the drag. Dragging stops following.
+    pub fn drag(&mut self, cursor: Option<Vec2>, screen_size: Vec2) {
+        let Some(cursor) = cursor else {
+            self.drag_anchor = None;
+            return;
+        };
+        let under_cursor = self.screen_to_world(cursor, screen_size);
+        if let Some(anchor) = self.drag_anchor {
+            self.target += anchor - under_cursor;
+        } else {
+            self.unfollow();
+            self.drag_anchor = Some(under_cursor);
+        }
+    }
+
+    /// Stops following and frames `area` of the world.
+    pub fn fit(&mut self, area: Rect) {
+        self.unfollow();
+        self.target = area.point() + area.size() / 2.0;
+        // How far the area reaches from its center along the screen's axes.
+        let reach = [vec2(area.w, area.h), vec2(area.w, -area.h)]
+            .map(|diagonal| rotate(diagonal / 2.0, -self.rotation).abs());
+        let reach = reach[0].max(reach[1]) * (1.0 + FIT_MARGIN);
+        // `zoom * reach` is the fraction of the half screen the area covers, at most 1 on both axes.
+        let aspect = self.zoom.y / self.zoom.x;
+        let zoom = (1.0 / reach.x).min(1.0 / (aspect * reach.y));
+        if zoom.is_finite() {
+            self.scale_zoom(zoom / self.zoom.x);
+        }
     }
 
     #[must_use]
@@ -66,78 +190,183 @@ impl Camera {
         )
     }
 
+    /// The macroquad camera the world is drawn with, `y` is flipped so it points up.
     #[must_use]
-    pub fn screen_to_world(&self, point: Vec2) -> Vec2 {
-        let (half_width, half_height) = (screen_width() / 2.0, screen_height() / 2.0);
-        // x, y offset from the center of the window.
-        let (x, y) = ((point.x - half_width), (point.y - half_height));
+    pub fn camera_2d(&self) -> Camera2D {
+        Camera2D {
+            target: self.target,
+            rotation: -self.rotation.to_degrees(),
+            zoom: vec2(self.zoom.x, -self.zoom.y),
+            ..Camera2D::default()
+        }
+    }
 
-        let (viewport_width, viewport_height) = self.viewport_size();
-        let (half_viewport_width, half_viewport_height) =
-            ((viewport_width / 2.0), (viewport_height / 2.0));
-
-        let result_x = map(
-            x,
-            -half_width,
-            half_width,
-            -half_viewport_width,
-            half_viewport_width,
-        );
-        let result_y = map(
-            y,
-            -half_height,
-            half_height,
-            -half_viewport_height,
-            half_viewport_height,
-        );
-        vec2(result_x + self.target.x, result_y + self.target.y)
+    /// Where `point`, in pixels on a screen of `screen_size`, is in the world as
+    /// `camera_2d` draws it.
+    #[must_use]
+    pub fn screen_to_world(&self, point: Vec2, screen_size: Vec2) -> Vec2 {
+        let half_screen = screen_size / 2.0;
+        // Offset from the view center in world units, before the camera's rotation.
+        let offset = (point - half_screen) / (self.zoom * half_screen);
+        self.target + rotate(offset, self.rotation)
+    }
+
+    /// Where `point` in the world is on a screen of `screen_size`, in pixels.
+    #[must_use]
+    pub fn world_to_screen(&self, point: Vec2, screen_size: Vec2) -> Vec2 {
+        let half_screen = screen_size / 2.0;
+        let offset = rotate(point - self.target, -self.rotation);
+        half_screen + offset * self.zoom * half_screen
     }
 
     #[must_use]
     pub fn mouse_world_position(&self) -> Vec2 {
         let mouse = mouse_position();
-        let mouse = vec2(mouse.0, mouse.1);
-        self.screen_to_world(mouse)
+        self.screen_to_world(vec2(mouse.0, mouse.1), screen_size())
     }
 }
 
+/// Size of the window in pixels.
+#[must_use]
+pub fn screen_size() -> Vec2 {
+    vec2(screen_width(), screen_height())
+}
+
 impl Default for Camera {
     fn default() -> Self {
         Self::new()
     }
 }
 
-pub fn top_down_camera_controls(camera: &mut Camera) {
-    // scroll
-    if is_key_down(KeyCode::Comma) {
-        // && is_key_pressed(KeyCode::LeftControl) {
-        camera.target.y -= 0.01 / camera.zoom.x;
-        camera.unfollow();
+/// Pans along the screen's axes and zooms around the view center, for keys held `dt` seconds.
+/// Panning stops following, zooming keeps it.
+pub fn top_down_camera_controls(camera: &mut Camera, controls: &InputMap, dt: f32) {
+    let mut pan = vec2(0.0, 0.0);
+    if controls.is_down(Action::PanUp) {
+        pan.y -= 1.0;
     }
-    if is_key_down(KeyCode::O) {
-        // && is_key_pressed(KeyCode::LeftControl) {
-        camera.target.y += 0.01 / camera.zoom.x;
-        camera.unfollow();
+    if controls.is_down(Action::PanDown) {
+        pan.y += 1.0;
     }
-    if is_key_down(KeyCode::A) {
-        // && is_key_pressed(KeyCode::LeftControl) {
-        camera.target.x -= 0.01 / camera.zoom.x;
-        camera.unfollow();
+    if controls.is_down(Action::PanLeft) {
+        pan.x -= 1.0;
     }
-    if is_key_down(KeyCode::E) {
-        // && is_key_pressed(KeyCode::LeftControl) {
-        camera.target.x += 0.01 / camera.zoom.x;
-        camera.unfollow();
+    if controls.is_down(Action::PanRight) {
+        pan.x += 1.0;
     }
-    // zoom
-    if is_key_down(KeyCode::PageUp) || is_key_down(KeyCode::Apostrophe) {
-        camera.zoom.x *= 0.98;
-        camera.zoom.y *= 0.98;
+    if pan != vec2(0.0, 0.0) {
+        let (view_width, _) = camera.viewport_size();
+        camera.target += rotate(pan, camera.rotation) * (PAN_SPEED * view_width * dt);
         camera.unfollow();
     }
-    if is_key_down(KeyCode::PageDown) || is_key_down(KeyCode::Period) {
-        camera.zoom.x /= 0.98;
-        camera.zoom.y /= 0.98;
-        camera.unfollow();
+    if controls.is_down(Action::ZoomOut) {
+        camera.scale_zoom(ZOOM_SPEED.powf(-dt));
+    }
+    if controls.is_down(Action::ZoomIn) {
+        camera.scale_zoom(ZOOM_SPEED.powf(dt));
+    }
+}
+
+/// Dragging with `Action::DragPan` held pans, the wheel zooms towards the cursor.
+/// While following, the wheel zooms around the view center instead.
+pub fn mouse_camera_controls(camera: &mut Camera, controls: &InputMap) {
+    let screen_size = screen_size();
+    let mouse = mouse_position();
+    let mouse = vec2(mouse.0, mouse.1);
+    camera.drag(
+        controls.is_down(Action::DragPan).then_some(mouse),
+        screen_size,
+    );
+    let (_, wheel) = mouse_wheel();
+    if wheel != 0.0 {
+        let towards = if camera.followed_pos.is_some() {
+            screen_size / 2.0
+        } else {
+            mouse
+        };
+        camera.zoom_towards(WHEEL_ZOOM.powf(wheel.signum()), towards, screen_size);
+    }
+}
+
+/// Moves `current` towards `goal` on a critically damped spring, the fastest way there
+/// without overshooting. `velocity` is the spring's state between calls.
+fn damp(current: f32, goal: f32, velocity: &mut f32, stiffness: f32, dt: f32) -> f32 {
+    // The exact step for a constant goal, except that e^-x, with x = stiffness * dt, is replaced
+    // by 1 / (1 + x + 0.48x² + 0.235x³). That is within 0.13% of e^-x up to x = 1, which covers
+    // the default stiffness at frame rates down to 8 fps, and never more than 0.019 off it.
+    // Unlike a truncated series it stays between 0 and 1 for any `dt`.
+    let x = stiffness * dt;
+    let decay = 1.0 / x.mul_add(x.mul_add(x.mul_add(0.235, 0.48), 1.0), 1.0);
+    let offset = current - goal;
+    let pull = stiffness.mul_add(offset, *velocity) * dt;
+    *velocity = stiffness.mul_add(-pull, *velocity) * decay;
+    (offset + pull).mul_add(decay, goal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::camera::Camera as _;
+    use macroquad::math::vec3;
+
+    fn screen() -> Vec2 {
+        vec2(800.0, 600.0)
+    }
+
+    /// A camera for an 800 by 600 screen, `Camera::new` needs a window to size itself.
+    fn camera(target: Vec2, rotation: f32, view_width: f32) -> Camera {
+        let zoom = 2.0 / view_width;
+        Camera {
+            target,
+            rotation,
+            zoom: vec2(zoom, zoom * screen().x / screen().y),
+            followed_pos: None,
+            followed_rot: None,
+            followed_velocity: vec2(0.0, 0.0),
+            follow: FollowSettings::default(),
+            velocity: vec2(0.0, 0.0),
+            angular_velocity: 0.0,
+            drag_anchor: None,
+        }
+    }
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 1e-3, "{a} != {b}");
+    }
+
+    #[test]
+    fn screen_points_round_trip_through_the_world() {
+        let camera = camera(vec2(1200.0, -340.0), 0.7, 3000.0);
+        for point in [vec2(0.0, 0.0), vec2(800.0, 600.0), vec2(123.0, 456.0)] {
+            let world = camera.screen_to_world(point, screen());
+            assert_near(camera.world_to_screen(world, screen()), point);
+        }
+    }
+
+    #[test]
+    fn the_screen_center_is_the_target() {
+        let camera = camera(vec2(-50.0, 75.0), -2.0, 400.0);
+        assert_near(
+            camera.screen_to_world(screen() / 2.0, screen()),
+            camera.target,
+        );
+        assert_near(
+            camera.world_to_screen(camera.target, screen()),
+            screen() / 2.0,
+        );
+    }
+
+    #[test]
+    fn screen_positions_match_what_is_drawn() {
+        let camera = camera(vec2(300.0, 200.0), 1.1, 900.0);
+        let matrix = camera.camera_2d().matrix();
+        for point in [vec2(300.0, 200.0), vec2(500.0, 150.0), vec2(-100.0, 650.0)] {
+            let clip = matrix.transform_point3(vec3(point.x, point.y, 0.0));
+            let drawn = vec2(
+                (clip.x / 2.0 + 0.5) * screen().x,
+                (0.5 - clip.y / 2.0) * screen().y,
+            );
+            assert_near(camera.world_to_screen(point, screen()), drawn);
+        }
     }
 }
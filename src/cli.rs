@@ -0,0 +1,300 @@
+use macroquad::prelude::*;
+
+use crate::common::*;
+use crate::export::{self, SvgOptions};
+use crate::noise::{Noise, NoiseSettings, NoiseSettingsError};
+use crate::palette::Palette;
+use crate::planet::{Planet, DEFAULT_SEA_LEVEL};
+use crate::world::SURFACE_RESOLUTION;
+
+pub const USAGE: &str = "\
+usage: generate [options]
+
+Renders a planet to a PNG without opening a window, or exports its outline.
+
+options:
+    --seed <n>           world seed, the planet matches the home planet of that world [0]
+    --radius <r>         base radius in pixels, mountains reach half of it higher [1500]
+    --resolution <n>     number of surface points [2048]
+    --noise <preset>     rocky, smooth or jagged [rocky]
+    --octaves <n>        override the preset's noise settings, give them after --noise
+    --frequency <f>
+    --amplitude <a>
+    --lacunarity <l>
+    --gain <g>
+    --palette <name>     earth, desert, ice or lava [earth]
+    --sea-level <level>  normalized altitude of the oceans, or none [0.27]
+    --caves              carve caves and overhangs
+    --no-fill            leave the ground and ocean out of SVGs
+    --no-strata          leave the interior layers out of SVGs
+    --output <path>      can be given more than once, the extension picks the format:
+                         .png image, .svg drawing, .csv or .json outline [planet.png]";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CliError {
+    Help,
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue { option: String, value: String },
+    Noise(NoiseSettingsError),
+    Write { path: String, message: String },
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Help => f.write_str(USAGE),
+            Self::UnknownOption(ref option) => write!(f, "unknown option {option}"),
+            Self::MissingValue(ref option) => write!(f, "{option} needs a value"),
+            Self::InvalidValue {
+                ref option,
+                ref value,
+            } => {
+                write!(f, "invalid value for {option}: {value}")
+            }
+            Self::Noise(ref error) => write!(f, "invalid noise settings: {error}"),
+            Self::Write {
+                ref path,
+                ref message,
+            } => write!(f, "couldn't write {path}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<NoiseSettingsError> for CliError {
+    fn from(error: NoiseSettingsError) -> Self {
+        Self::Noise(error)
+    }
+}
+
+/// What `generate` was asked to render.
+struct Options {
+    seed: u64,
+    radius: f32,
+    resolution: usize,
+    noise: NoiseSettings,
+    palette: Palette,
+    sea_level: Option<f32>,
+    caves: bool,
+    svg: SvgOptions,
+    outputs: Vec<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut options = Self {
+            seed: 0,
+            radius: 1500.0,
+            resolution: SURFACE_RESOLUTION,
+            noise: NoiseSettings::default(),
+            palette: Palette::default(),
+            sea_level: Some(DEFAULT_SEA_LEVEL),
+            caves: false,
+            svg: SvgOptions::default(),
+            outputs: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(option) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| CliError::MissingValue(option.clone()))
+            };
+            match option.as_str() {
+                "--help" | "-h" => return Err(CliError::Help),
+                "--seed" => options.seed = parse(option, value()?)?,
+                "--radius" => options.radius = parse_positive(option, value()?)?,
+                "--resolution" => options.resolution = parse(option, value()?)?,
+                "--noise" => {
+                    let name = value()?;
+                    options.noise =
+                        NoiseSettings::preset(name).ok_or_else(|| invalid(option, name))?;
+                }
+                "--octaves" => options.noise.octaves = parse(option, value()?)?,
+                "--frequency" => {
+                    let frequency = parse(option, value()?)?;
+                    options.noise.x_frequency = frequency;
+                    options.noise.y_frequency = frequency;
+                }
+                "--amplitude" => options.noise.amplitude = parse(option, value()?)?,
+                "--lacunarity" => options.noise.lacunarity = parse(option, value()?)?,
+                "--gain" => options.noise.gain = parse(option, value()?)?,
+                "--palette" => {
+                    let name = value()?;
+                    options.palette = Palette::preset(name).ok_or_else(|| invalid(option, name))?;
+                }
+                "--sea-level" => {
+                    let level = value()?;
+                    options.sea_level = if level == "none" {
+                        None
+                    } else {
+                        Some(parse_finite(option, level)?)
+                    };
+                }
+                "--caves" => options.caves = true,
+                "--no-fill" => options.svg.fill = false,
+                "--no-strata" => options.svg.strata = false,
+                "--output" | "-o" => options.outputs.push(value()?.clone()),
+                _ => return Err(CliError::UnknownOption(option.clone())),
+            }
+        }
+        options.noise.validate()?;
+        if options.outputs.is_empty() {
+            options.outputs.push(String::from("planet.png"));
+        }
+        Ok(options)
+    }
+}
+
+fn invalid(option: &str, value: &str) -> CliError {
+    CliError::InvalidValue {
+        option: option.to_owned(),
+        value: value.to_owned(),
+    }
+}
+
+fn parse<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, CliError> {
+    value.parse().map_err(|_| invalid(option, value))
+}
+
+fn parse_finite(option: &str, value: &str) -> Result<f32, CliError> {
+    let number: f32 = parse(option, value)?;
+    if number.is_finite() {
+        Ok(number)
+    } else {
+        Err(invalid(option, value))
+    }
+}
+
+fn parse_positive(option: &str, value: &str) -> Result<f32, CliError> {
+    let number = parse_finite(option, value)?;
+    if number > 0.0 {
+        Ok(number)
+    } else {
+        Err(invalid(option, value))
+    }
+}
+
+/// The lowercase extension of `path`, which picks the format it is written in.
+fn extension(path: &str) -> Option<String> {
+    std::path::Path::new(path)
+        .extension()
+        .and_then(std::ffi::OsStr::to_str)
+        .map(str::to_ascii_lowercase)
+}
+
+/// Writes `image` as a PNG, its first row at the top.
+fn write_png(path: &str, image: &Image) -> Result<(), CliError> {
+    ::image::save_buffer(
+        path,
+        &image.bytes,
+        u32::from(image.width),
+        u32::from(image.height),
+        ::image::ColorType::Rgba8,
+    )
+    .map_err(|error| CliError::Write {
+        path: path.to_owned(),
+        message: error.to_string(),
+    })
+}
+
+/// Renders the planet described by `args` to a PNG, without a window or a GPU.
+///
+/// # Errors
+///
+/// `CliError::Help` when asked for the usage, and the other variants for options that
+/// don't parse or outputs that can't be written.
+pub fn generate(args: &[String]) -> Result<(), CliError> {
+    let options = Options::parse(args)?;
+    // The same noise the world gives its first planet, so seeds can be tried out in the game.
+    let noise = Noise::new(hash_seed(options.seed, 0), options.noise)?;
+    let mut planet = Planet::new(vec2(0.0, 0.0), options.radius, options.resolution, &noise);
+    if options.caves {
+        planet.carve_caves(&noise);
+    }
+    planet.palette = options.palette;
+    planet.sea_level = options.sea_level;
+    // Rendered once for all the PNGs, and before writing anything so a radius too big for
+    // an image doesn't leave the other outputs written.
+    let wants_png = options
+        .outputs
+        .iter()
+        .any(|path| extension(path).as_deref() == Some("png"));
+    let image = if wants_png {
+        let image = Planet::as_image(&planet).ok_or_else(|| {
+            invalid(
+                "--radius",
+                &format!("{} is too big for an image", options.radius),
+            )
+        })?;
+        Some(image)
+    } else {
+        None
+    };
+    for path in &options.outputs {
+        let text = match (extension(path).as_deref(), image.as_ref()) {
+            (Some("svg"), _) => export::to_svg(&planet, options.svg),
+            (Some("csv"), _) => export::to_csv(&planet),
+            (Some("json"), _) => export::to_json(&planet),
+            (Some("png"), Some(image)) => {
+                write_png(path, image)?;
+                println!("wrote {path} ({}x{})", image.width, image.height);
+                continue;
+            }
+            _ => return Err(invalid("--output", path)),
+        };
+        std::fs::write(path, text).map_err(|error| CliError::Write {
+            path: path.clone(),
+            message: error.to_string(),
+        })?;
+        println!("wrote {path}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&arg| arg.to_owned()).collect()
+    }
+
+    #[test]
+    fn unwritable_pngs_are_errors() {
+        let path = std::env::temp_dir().join("planet_shaper_missing_directory/planet.png");
+        let path = path.to_string_lossy();
+        let result = generate(&args(&[
+            "--radius",
+            "20",
+            "--resolution",
+            "64",
+            "-o",
+            &path,
+        ]));
+        assert!(matches!(result, Err(CliError::Write { .. })), "{result:?}");
+    }
+
+    #[test]
+    fn radii_too_big_for_an_image_are_rejected_before_writing() {
+        let svg = std::env::temp_dir().join("planet_shaper_too_big.svg");
+        std::fs::remove_file(&svg).ok();
+        let svg = svg.to_string_lossy();
+        let result = generate(&args(&[
+            "--radius",
+            "40000",
+            "--resolution",
+            "64",
+            "-o",
+            &svg,
+            "-o",
+            "planet_shaper_too_big.png",
+        ]));
+        assert!(
+            matches!(result, Err(CliError::InvalidValue { ref option, .. }) if option == "--radius"),
+            "{result:?}"
+        );
+        assert!(!std::path::Path::new(&*svg).exists());
+    }
+}
@@ -0,0 +1,184 @@
+use macroquad::math::Vec2;
+
+/// Where a moving point first touched a surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    pub point: Vec2,
+    /// Unit normal of the touched segment, facing the side the point came from.
+    pub normal: Vec2,
+    /// Fraction of the sweep travelled before touching, in `[0, 1]`.
+    pub time: f32,
+}
+
+impl Contact {
+    /// Angle between the surface and flat ground, flat ground being perpendicular to `up`.
+    #[must_use]
+    pub fn slope(&self, up: Vec2) -> f32 {
+        let up = up.normalize_or_zero();
+        if up == Vec2::new(0.0, 0.0) {
+            return 0.0;
+        }
+        self.normal.angle_between(up).abs()
+    }
+
+    /// The contact point moved `skin` off the surface, so the next sweep starts outside.
+    #[must_use]
+    pub fn push_out(&self, skin: f32) -> Vec2 {
+        self.point + self.normal * skin
+    }
+
+    /// `velocity` without the part going into the surface.
+    #[must_use]
+    pub fn slide(&self, velocity: Vec2) -> Vec2 {
+        let into = velocity.dot(self.normal);
+        if into < 0.0 {
+            velocity - self.normal * into
+        } else {
+            velocity
+        }
+    }
+}
+
+/// Where segments `a` and `b` cross, as fractions along `a` and along `b`.
+#[must_use]
+pub fn segment_intersection(a: (Vec2, Vec2), b: (Vec2, Vec2)) -> Option<(f32, f32)> {
+    let a_direction = a.1 - a.0;
+    let b_direction = b.1 - b.0;
+    let denominator = a_direction.perp_dot(b_direction);
+    if denominator.abs() <= f32::EPSILON {
+        // Parallel, touching along a line is left to the neighbouring segments.
+        return None;
+    }
+    let offset = b.0 - a.0;
+    let along_a = offset.perp_dot(b_direction) / denominator;
+    let along_b = offset.perp_dot(a_direction) / denominator;
+    ((0.0..=1.0).contains(&along_a) && (0.0..=1.0).contains(&along_b)).then_some((along_a, along_b))
+}
+
+/// Sweeps a point from `from` to `to` through `segments`, returning the first contact.
+pub fn sweep<I>(segments: I, from: Vec2, to: Vec2) -> Option<Contact>
+where
+    I: IntoIterator<Item = (Vec2, Vec2)>,
+{
+    let motion = to - from;
+    if motion.length_squared() <= f32::EPSILON {
+        return None;
+    }
+    let mut first: Option<Contact> = None;
+    for segment in segments {
+        let Some((time, _)) = segment_intersection((from, to), segment) else {
+            continue;
+        };
+        if first.is_some_and(|contact| contact.time <= time) {
+            continue;
+        }
+        let tangent = segment.1 - segment.0;
+        let mut normal = Vec2::new(-tangent.y, tangent.x).normalize_or_zero();
+        if normal.dot(motion) > 0.0 {
+            normal = -normal;
+        }
+        first = Some(Contact {
+            point: from + motion * time,
+            normal,
+            time,
+        });
+    }
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::vec2;
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        a.distance(b) < 1e-5
+    }
+
+    #[test]
+    fn parallel_and_collinear_segments_dont_intersect() {
+        let a = (vec2(0.0, 0.0), vec2(2.0, 0.0));
+        assert_eq!(
+            segment_intersection(a, (vec2(0.0, 1.0), vec2(2.0, 1.0))),
+            None
+        );
+        assert_eq!(
+            segment_intersection(a, (vec2(1.0, 0.0), vec2(3.0, 0.0))),
+            None
+        );
+    }
+
+    #[test]
+    fn intersections_at_the_ends_count() {
+        let a = (vec2(0.0, 0.0), vec2(2.0, 0.0));
+        let start = segment_intersection(a, (vec2(0.0, -1.0), vec2(0.0, 1.0)));
+        assert_eq!(start, Some((0.0, 0.5)));
+        let end = segment_intersection(a, (vec2(2.0, -1.0), vec2(2.0, 1.0)));
+        assert_eq!(end, Some((1.0, 0.5)));
+        assert_eq!(
+            segment_intersection(a, (vec2(2.5, -1.0), vec2(2.5, 1.0))),
+            None
+        );
+    }
+
+    #[test]
+    fn sweeps_stop_at_the_closest_segment() {
+        let wall = |x: f32| (vec2(x, -1.0), vec2(x, 1.0));
+        let contact = sweep(
+            [wall(7.0), wall(3.0), wall(5.0)],
+            vec2(0.0, 0.0),
+            vec2(10.0, 0.0),
+        )
+        .expect("the sweep should hit a wall");
+        assert!((contact.time - 0.3).abs() < 1e-6);
+        assert!(close(contact.point, vec2(3.0, 0.0)));
+        assert!(close(contact.normal, vec2(-1.0, 0.0)));
+        assert_eq!(sweep([wall(7.0)], vec2(0.0, 0.0), vec2(5.0, 0.0)), None);
+        assert_eq!(sweep([wall(0.0)], vec2(0.0, 0.0), vec2(0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn normals_face_where_the_sweep_came_from() {
+        let floor = (vec2(-1.0, 0.0), vec2(1.0, 0.0));
+        let from_above = sweep([floor], vec2(0.0, 1.0), vec2(0.0, -1.0))
+            .expect("the sweep should hit the floor");
+        assert!(close(from_above.normal, vec2(0.0, 1.0)));
+        let from_below = sweep([floor], vec2(0.0, -1.0), vec2(0.0, 1.0))
+            .expect("the sweep should hit the floor");
+        assert!(close(from_below.normal, vec2(0.0, -1.0)));
+    }
+
+    #[test]
+    fn sliding_down_a_slope() {
+        // Ground rising at 45 degrees to the left, the normal points up and to the right.
+        let slope = sweep(
+            [(vec2(-2.0, 2.0), vec2(2.0, -2.0))],
+            vec2(1.0, 1.0),
+            vec2(-1.0, -1.0),
+        )
+        .expect("the sweep should hit the slope");
+        let normal = vec2(1.0, 1.0).normalize();
+        assert!(close(slope.normal, normal));
+        assert!((slope.slope(vec2(0.0, 1.0)) - std::f32::consts::FRAC_PI_4).abs() < 1e-5);
+        assert!(slope.slope(vec2(0.0, 0.0)).abs() < f32::EPSILON);
+        // Walking left into the slope keeps only the part along it.
+        assert!(close(slope.slide(vec2(-1.0, 0.0)), vec2(-0.5, 0.5)));
+        // Moving away from it is left alone.
+        assert!(close(slope.slide(vec2(1.0, 0.0)), vec2(1.0, 0.0)));
+    }
+
+    #[test]
+    fn pushing_out_along_the_normal() {
+        let contact = Contact {
+            point: vec2(1.0, 1.0),
+            normal: vec2(1.0, 1.0).normalize(),
+            time: 0.5,
+        };
+        let pushed = contact.push_out(0.5);
+        assert!(close(
+            pushed,
+            vec2(1.0, 1.0) + vec2(1.0, 1.0).normalize() * 0.5
+        ));
+        assert!((pushed.distance(contact.point) - 0.5).abs() < 1e-6);
+    }
+}
@@ -41,3 +41,122 @@ pub fn rotate(vector: Vec2, radians: f32) -> Vec2 {
     let rotation_matrix = Mat3::from_rotation_z(radians);
     rotation_matrix.transform_vector2(vector)
 }
+
+/// The shortest turn from `from` to `to`, in radians between -PI and PI.
+#[must_use]
+pub fn angle_difference(from: f32, to: f32) -> f32 {
+    use std::f32::consts::{PI, TAU};
+    (to - from + PI).rem_euclid(TAU) - PI
+}
+
+/// Angle of the `index`th of `len` samples spread evenly around a full turn from 0.
+#[must_use]
+pub fn ring_angle(index: usize, len: usize) -> f32 {
+    count_to_f32(index) * std::f32::consts::TAU / count_to_f32(len)
+}
+
+/// Where `angle` falls among `len` samples spread evenly around a full turn from 0:
+/// the sample before it, the sample after it and how far between the two it is.
+#[must_use]
+pub fn ring_position(angle: f32, len: usize) -> (usize, usize, f32) {
+    let position =
+        angle.rem_euclid(std::f32::consts::TAU) / std::f32::consts::TAU * count_to_f32(len);
+    let index = floor_index(position)
+        .and_then(|index| index.checked_rem(len))
+        .unwrap_or(0);
+    (
+        index,
+        (index + 1).checked_rem(len).unwrap_or(0),
+        position.fract(),
+    )
+}
+
+/// Past 2^24 an `f32` no longer holds every integer, so it can't count or index anything exactly.
+const MAX_EXACT_INTEGER: f32 = 16_777_216.0;
+
+/// `count` as an `f32`, the counts of samples and cells here are far below `MAX_EXACT_INTEGER`.
+#[must_use]
+#[allow(
+    clippy::as_conversions,
+    clippy::cast_precision_loss,
+    reason = "there is no `From<usize>` for `f32`, and the counts converted here are exact"
+)]
+pub const fn count_to_f32(count: usize) -> f32 {
+    count as f32
+}
+
+/// `value` rounded down to a whole index, `None` when it is negative, not finite or past
+/// `MAX_EXACT_INTEGER`.
+#[must_use]
+#[allow(
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    reason = "the value is checked to be a whole number within `u32` before the cast"
+)]
+pub fn floor_index(value: f32) -> Option<usize> {
+    let value = value.floor();
+    if !(0.0..=MAX_EXACT_INTEGER).contains(&value) {
+        return None;
+    }
+    usize::try_from(value as u32).ok()
+}
+
+/// `length` rounded up to whole pixels, `None` when negative or past what an image can hold.
+#[must_use]
+pub fn pixels(length: f32) -> Option<u16> {
+    floor_index(length.ceil()).and_then(|length| u16::try_from(length).ok())
+}
+
+/// Derives an independent, reproducible value from `seed` for the given `stream` (splitmix64).
+#[must_use]
+pub const fn hash_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed.wrapping_add(stream.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Maps the `stream`th value derived from `seed` into `[low, high)`.
+#[must_use]
+pub fn seeded_range(seed: u64, stream: u64, low: f32, high: f32) -> f32 {
+    // The top 24 bits, as many as an `f32` holds exactly.
+    let [first, second, third, ..] = hash_seed(seed, stream).to_be_bytes();
+    let bits =
+        f32::from(first).mul_add(65_536.0, f32::from(second).mul_add(256.0, f32::from(third)));
+    let unit = bits / 16_777_216.0;
+    lerp(low, high, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floor_index_rejects_what_is_not_an_index() {
+        assert_eq!(floor_index(3.9), Some(3));
+        assert_eq!(floor_index(0.0), Some(0));
+        assert_eq!(floor_index(-0.5), None);
+        assert_eq!(floor_index(f32::NAN), None);
+        assert_eq!(floor_index(f32::INFINITY), None);
+        assert_eq!(floor_index(1e9), None);
+    }
+
+    #[test]
+    fn ring_positions_wrap_around() {
+        let quarter = std::f32::consts::FRAC_PI_2;
+        assert_eq!(ring_position(0.0, 4), (0, 1, 0.0));
+        assert_eq!(ring_position(quarter * 3.5, 4), (3, 0, 0.5));
+        assert_eq!(ring_position(-quarter * 0.5, 4), (3, 0, 0.5));
+        assert_eq!(ring_position(1.0, 0), (0, 0, 0.0));
+    }
+
+    #[test]
+    fn pixels_round_up_and_refuse_what_does_not_fit() {
+        assert_eq!(pixels(10.2), Some(11));
+        assert_eq!(pixels(65_535.0), Some(u16::MAX));
+        assert_eq!(pixels(65_535.5), None);
+        assert_eq!(pixels(-1.0), None);
+        assert_eq!(pixels(f32::NAN), None);
+    }
+}
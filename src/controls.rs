@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use macroquad::prelude::*;
+use serde::Deserialize;
+
+/// Where custom bindings are read from at startup.
+pub const CONTROLS_PATH: &str = "controls.json";
+/// Highest key code miniquad maps to a key.
+const MAX_KEY_CODE: u32 = 512;
+
+/// Something the player can do, triggered by whatever an `InputMap` binds to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    NextMovementMode,
+    /// Digs where the mouse is, or builds while `Build` is held.
+    Dig,
+    Build,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    /// Held to pan by dragging the mouse.
+    DragPan,
+    ZoomIn,
+    ZoomOut,
+    FollowPlayer,
+    FitPlanet,
+    Regenerate,
+    CopySeed,
+    EnterSeed,
+    NextNoisePreset,
+    ShowNoise,
+    NextPalette,
+    NextLayout,
+    SpawnPlanet,
+    RemovePlanet,
+    ToggleCaves,
+    RaiseSeaLevel,
+    LowerSeaLevel,
+    QuickSave,
+    QuickLoad,
+}
+
+/// A key or mouse button an action is bound to.
+/// macroquad doesn't read gamepads yet, their buttons would be another kind of binding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    fn is_down(self) -> bool {
+        match self {
+            Self::Key(key) => is_key_down(key),
+            Self::Mouse(button) => is_mouse_button_down(button),
+        }
+    }
+
+    fn is_pressed(self) -> bool {
+        match self {
+            Self::Key(key) => is_key_pressed(key),
+            Self::Mouse(button) => is_mouse_button_pressed(button),
+        }
+    }
+
+    /// Reads a binding the way `Display` writes it, like `W`, `PageUp` or `MouseRight`.
+    #[must_use]
+    pub fn parse(name: &str) -> Option<Self> {
+        if let Some(button) = name.strip_prefix("Mouse") {
+            return [MouseButton::Left, MouseButton::Right, MouseButton::Middle]
+                .into_iter()
+                .find(|&candidate| format!("{candidate:?}") == button)
+                .map(Self::Mouse);
+        }
+        (0..MAX_KEY_CODE)
+            .map(KeyCode::from)
+            .filter(|&key| key != KeyCode::Unknown)
+            .find(|&key| format!("{key:?}") == name)
+            .map(Self::Key)
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Key(key) => write!(f, "{key:?}"),
+            Self::Mouse(button) => write!(f, "Mouse{button:?}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum InputMapError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    UnknownLayout(String),
+    UnknownBinding(String),
+}
+
+impl std::fmt::Display for InputMapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Io(ref error) => write!(f, "{error}"),
+            Self::Format(ref error) => write!(f, "malformed controls: {error}"),
+            Self::UnknownLayout(ref name) => write!(f, "unknown layout {name}"),
+            Self::UnknownBinding(ref name) => write!(f, "unknown key or button {name}"),
+        }
+    }
+}
+
+impl std::error::Error for InputMapError {}
+
+impl From<std::io::Error> for InputMapError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for InputMapError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Format(error)
+    }
+}
+
+/// The controls file: a layout to start from, and actions to bind differently.
+/// `{"layout": "dvorak", "bindings": {"Jump": ["Space", "MouseMiddle"]}}`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ControlsFile {
+    layout: Option<String>,
+    #[serde(default)]
+    bindings: HashMap<Action, Vec<String>>,
+}
+
+/// Which keys and buttons trigger each action.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputMap {
+    /// The layout preset the bindings started from.
+    pub layout: &'static str,
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl InputMap {
+    /// Movement on WASD, zoom on Q and E.
+    #[must_use]
+    pub fn qwerty() -> Self {
+        Self::with_common_bindings(
+            "qwerty",
+            [
+                (Action::MoveUp, KeyCode::W),
+                (Action::MoveDown, KeyCode::S),
+                (Action::MoveLeft, KeyCode::A),
+                (Action::MoveRight, KeyCode::D),
+                (Action::ZoomOut, KeyCode::Q),
+                (Action::ZoomIn, KeyCode::E),
+            ],
+        )
+    }
+
+    /// The same keys as `qwerty` where they sit on a Dvorak keyboard.
+    #[must_use]
+    pub fn dvorak() -> Self {
+        Self::with_common_bindings(
+            "dvorak",
+            [
+                (Action::MoveUp, KeyCode::Comma),
+                (Action::MoveDown, KeyCode::O),
+                (Action::MoveLeft, KeyCode::A),
+                (Action::MoveRight, KeyCode::E),
+                (Action::ZoomOut, KeyCode::Apostrophe),
+                (Action::ZoomIn, KeyCode::Period),
+            ],
+        )
+    }
+
+    #[must_use]
+    pub fn presets() -> [Self; 2] {
+        [Self::qwerty(), Self::dvorak()]
+    }
+
+    #[must_use]
+    pub fn preset(name: &str) -> Option<Self> {
+        Self::presets()
+            .into_iter()
+            .find(|controls| controls.layout == name)
+    }
+
+    /// `layout_keys` plus the bindings that are the same on every layout.
+    fn with_common_bindings(layout: &'static str, layout_keys: [(Action, KeyCode); 6]) -> Self {
+        let keys = [
+            (Action::Jump, KeyCode::Space),
+            (Action::NextMovementMode, KeyCode::M),
+            (Action::Build, KeyCode::LeftShift),
+            (Action::PanUp, KeyCode::Up),
+            (Action::PanDown, KeyCode::Down),
+            (Action::PanLeft, KeyCode::Left),
+            (Action::PanRight, KeyCode::Right),
+            (Action::ZoomIn, KeyCode::PageDown),
+            (Action::ZoomOut, KeyCode::PageUp),
+            (Action::FollowPlayer, KeyCode::F),
+            (Action::FitPlanet, KeyCode::Home),
+            (Action::Regenerate, KeyCode::R),
+            (Action::CopySeed, KeyCode::F1),
+            (Action::EnterSeed, KeyCode::F2),
+            (Action::NextNoisePreset, KeyCode::F3),
+            (Action::ShowNoise, KeyCode::F4),
+            (Action::QuickSave, KeyCode::F5),
+            (Action::NextLayout, KeyCode::F6),
+            (Action::QuickLoad, KeyCode::F9),
+            (Action::NextPalette, KeyCode::P),
+            (Action::SpawnPlanet, KeyCode::N),
+            (Action::RemovePlanet, KeyCode::Delete),
+            (Action::ToggleCaves, KeyCode::C),
+            (Action::RaiseSeaLevel, KeyCode::RightBracket),
+            (Action::LowerSeaLevel, KeyCode::LeftBracket),
+        ];
+        let buttons = [
+            (Action::Dig, MouseButton::Left),
+            (Action::DragPan, MouseButton::Right),
+            (Action::DragPan, MouseButton::Middle),
+        ];
+        let mut bindings: HashMap<Action, Vec<Binding>> = HashMap::new();
+        // Layout keys come first, so they are the ones shown in hints.
+        let keys = layout_keys.into_iter().chain(keys);
+        for (action, binding) in keys
+            .map(|(action, key)| (action, Binding::Key(key)))
+            .chain(buttons.map(|(action, button)| (action, Binding::Mouse(button))))
+        {
+            bindings.entry(action).or_default().push(binding);
+        }
+        Self { layout, bindings }
+    }
+
+    /// Reads the controls file at `path`.
+    pub fn load(path: &Path) -> Result<Self, InputMapError> {
+        let json = std::fs::read_to_string(path)?;
+        let file: ControlsFile = serde_json::from_str(&json)?;
+        let mut controls = match file.layout {
+            Some(layout) => Self::preset(&layout).ok_or(InputMapError::UnknownLayout(layout))?,
+            None => Self::default(),
+        };
+        for (action, names) in file.bindings {
+            let bindings = names
+                .into_iter()
+                .map(|name| Binding::parse(&name).ok_or(InputMapError::UnknownBinding(name)))
+                .collect::<Result<_, _>>()?;
+            controls.bind(action, bindings);
+        }
+        Ok(controls)
+    }
+
+    /// Replaces what triggers `action`, an empty list unbinds it.
+    pub fn bind(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.bindings.insert(action, bindings);
+    }
+
+    fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    #[must_use]
+    pub fn is_down(&self, action: Action) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| binding.is_down())
+    }
+
+    #[must_use]
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| binding.is_pressed())
+    }
+
+    /// What to press for `action`, for hints.
+    #[must_use]
+    pub fn hint(&self, action: Action) -> String {
+        self.bindings(action)
+            .first()
+            .map_or_else(|| String::from("unbound"), ToString::to_string)
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self::qwerty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads `json` as a controls file, from a file of its own named after `name`.
+    fn load(name: &str, json: &str) -> Result<InputMap, InputMapError> {
+        let path = std::env::temp_dir().join(format!("planet_shaper_controls_{name}.json"));
+        std::fs::write(&path, json)?;
+        let controls = InputMap::load(&path);
+        std::fs::remove_file(&path).ok();
+        controls
+    }
+
+    #[test]
+    fn bindings_parse_back_from_their_names() {
+        let keys = (0..MAX_KEY_CODE)
+            .map(KeyCode::from)
+            .filter(|&key| key != KeyCode::Unknown)
+            .map(Binding::Key);
+        let buttons =
+            [MouseButton::Left, MouseButton::Right, MouseButton::Middle].map(Binding::Mouse);
+        for binding in keys.chain(buttons) {
+            assert_eq!(Binding::parse(&binding.to_string()), Some(binding));
+        }
+        assert_eq!(
+            Binding::parse("PageUp"),
+            Some(Binding::Key(KeyCode::PageUp))
+        );
+        assert_eq!(
+            Binding::parse("MouseRight"),
+            Some(Binding::Mouse(MouseButton::Right))
+        );
+        for unknown in [
+            "",
+            "w",
+            "Spacebar",
+            "Mouse",
+            "MouseUnknown",
+            "Mouse4",
+            "Unknown",
+        ] {
+            assert_eq!(Binding::parse(unknown), None, "{unknown:?} shouldn't parse");
+        }
+    }
+
+    #[test]
+    fn presets_bind_nothing_to_two_actions() {
+        for preset in InputMap::presets() {
+            let mut bound: Vec<(Binding, Action)> = Vec::new();
+            for (&action, bindings) in &preset.bindings {
+                for &binding in bindings {
+                    let other = bound.iter().find(|&&(other, _)| other == binding);
+                    assert!(
+                        other.is_none_or(|&(_, other)| other == action),
+                        "{} binds {binding} to {other:?} and {action:?}",
+                        preset.layout
+                    );
+                    bound.push((binding, action));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn controls_files_start_from_a_layout_and_rebind() {
+        let controls = load(
+            "rebind",
+            r#"{"layout": "dvorak", "bindings": {"Jump": ["Enter", "MouseMiddle"], "Dig": []}}"#,
+        )
+        .expect("the controls should load");
+        assert_eq!(controls.layout, "dvorak");
+        assert_eq!(
+            controls.bindings(Action::Jump),
+            [
+                Binding::Key(KeyCode::Enter),
+                Binding::Mouse(MouseButton::Middle)
+            ]
+        );
+        assert!(controls.bindings(Action::Dig).is_empty());
+        assert_eq!(
+            controls.bindings(Action::MoveUp),
+            InputMap::dvorak().bindings(Action::MoveUp)
+        );
+        let defaults = load("defaults", "{}").expect("an empty file should load");
+        assert_eq!(defaults, InputMap::default());
+    }
+
+    #[test]
+    fn broken_controls_files_are_errors() {
+        assert!(matches!(
+            load("unknown_binding", r#"{"bindings": {"Jump": ["Spacebar"]}}"#),
+            Err(InputMapError::UnknownBinding(ref name)) if name == "Spacebar"
+        ));
+        assert!(matches!(
+            load("unknown_layout", r#"{"layout": "colemak"}"#),
+            Err(InputMapError::UnknownLayout(ref name)) if name == "colemak"
+        ));
+        assert!(matches!(
+            load("unknown_action", r#"{"bindings": {"Fly": ["F"]}}"#),
+            Err(InputMapError::Format(_))
+        ));
+        assert!(matches!(
+            InputMap::load(Path::new("planet_shaper_missing_controls.json")),
+            Err(InputMapError::Io(_))
+        ));
+    }
+}
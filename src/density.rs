@@ -0,0 +1,584 @@
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+
+use crate::collision::{self, Contact};
+use crate::common::*;
+use crate::planet::TerrainEdit;
+
+/// Terrain stored as density samples on a grid, solid where the density is positive.
+/// Unlike the radial surface it can describe caves, tunnels and overhangs.
+#[derive(Clone)]
+pub struct DensityField {
+    origin: Vec2,
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    values: Vec<f32>,
+
+    /// Boundary between solid and empty, extracted with marching squares.
+    pub contour: Vec<(Vec2, Vec2)>,
+    /// Where each cell's segments start in `contour`, cells are stored row by row
+    /// and the last entry is where the segments end.
+    cell_starts: Vec<usize>,
+}
+
+/// The density along one horizontal line, see `DensityField::row`.
+pub struct DensityRow<'a> {
+    field: &'a DensityField,
+    /// The density at each column of the grid, interpolated down to the line. Empty when the
+    /// line misses the grid.
+    columns: Vec<f32>,
+}
+
+impl DensityRow<'_> {
+    /// Density at `x` on the line, the same as `DensityField::sample` there.
+    #[must_use]
+    pub fn sample(&self, x: f32) -> f32 {
+        let field = self.field;
+        let (column, fx) = field.locate(x, field.origin.x, field.columns);
+        let value = |column: usize| self.columns.get(column).copied();
+        match (value(column), value(column + 1), fx) {
+            (Some(left), Some(right), Some(fx)) => lerp(left, right, fx),
+            _ => -1.0,
+        }
+    }
+
+    #[must_use]
+    pub fn is_solid(&self, x: f32) -> bool {
+        self.sample(x) > 0.0
+    }
+
+    /// The column of the grid cell `x` is in, `None` off the grid.
+    #[must_use]
+    pub fn cell(&self, x: f32) -> Option<usize> {
+        let field = self.field;
+        let (column, fx) = field.locate(x, field.origin.x, field.columns);
+        (!self.columns.is_empty() && fx.is_some()).then_some(column)
+    }
+
+    /// When the density is positive on both edges of the cell at `column`, the interpolation
+    /// can't bring it down to zero anywhere in between, so the cell is solid throughout. Then
+    /// this is the last column of the run of such cells the cell starts.
+    #[must_use]
+    pub fn solid_through(&self, column: usize) -> Option<usize> {
+        // Products of subnormal densities could round to zero.
+        let positive = |column: usize| {
+            self.columns
+                .get(column)
+                .is_some_and(|&density| density >= f32::MIN_POSITIVE)
+        };
+        let solid = |column: usize| positive(column) && positive(column + 1);
+        solid(column).then(|| {
+            (column..self.columns.len())
+                .take_while(|&column| solid(column))
+                .last()
+                .unwrap_or(column)
+        })
+    }
+}
+
+impl DensityField {
+    /// Samples `density` every `cell_size` units over `bounds`.
+    pub fn new(bounds: Rect, cell_size: f32, density: impl Fn(Vec2) -> f32) -> Self {
+        let cells = |length: f32| {
+            floor_index((length / cell_size).ceil()).expect("the grid should have a sane size")
+        };
+        let (columns, rows) = (cells(bounds.w) + 1, cells(bounds.h) + 1);
+        let origin = bounds.point();
+        let mut values = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                values.push(density(
+                    origin + vec2(count_to_f32(column), count_to_f32(row)) * cell_size,
+                ));
+            }
+        }
+        Self::from_values(origin, cell_size, columns, values)
+            .expect("the sampled grid should be at least two cells wide and tall")
+    }
+
+    /// Rebuilds a field from density values stored row by row, `columns` to a row.
+    /// `None` when the values don't fill a grid of at least two by two samples.
+    #[must_use]
+    pub fn from_values(
+        origin: Vec2,
+        cell_size: f32,
+        columns: usize,
+        values: Vec<f32>,
+    ) -> Option<Self> {
+        let rows = values.len().checked_div(columns).unwrap_or(0);
+        let valid = cell_size.is_finite()
+            && cell_size > 0.0
+            && columns >= 2
+            && rows >= 2
+            && rows * columns == values.len();
+        if !valid {
+            return None;
+        }
+        let mut field = Self {
+            origin,
+            cell_size,
+            columns,
+            rows,
+            values,
+            contour: Vec::new(),
+            cell_starts: Vec::new(),
+        };
+        field.march();
+        Some(field)
+    }
+
+    pub const fn origin(&self) -> Vec2 {
+        self.origin
+    }
+
+    pub const fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub const fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// The density samples row by row, as `from_values` takes them.
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// The density sample at a grid corner, negative outside of the grid.
+    fn value(&self, column: usize, row: usize) -> f32 {
+        self.values
+            .get(row * self.columns + column)
+            .copied()
+            .unwrap_or(-1.0)
+    }
+
+    /// The cell `point` is in, clamped to the grid.
+    fn cell_at(&self, point: Vec2) -> (usize, usize) {
+        (
+            self.locate(point.x, self.origin.x, self.columns).0,
+            self.locate(point.y, self.origin.y, self.rows).0,
+        )
+    }
+
+    /// The cell `position` is in along one axis of the grid, which starts at `origin` and has
+    /// `samples` samples, and how far into the cell it is. `None` for positions off the grid.
+    fn locate(&self, position: f32, origin: f32, samples: usize) -> (usize, Option<f32>) {
+        let grid = (position - origin) / self.cell_size;
+        // Positions past the far edge end up in the last cell.
+        let last = samples - 2;
+        let cell = floor_index(grid.max(0.0)).map_or(last, |index| index.min(last));
+        let on_grid = (0.0..=count_to_f32(samples - 1)).contains(&grid);
+        (cell, on_grid.then(|| grid - count_to_f32(cell)))
+    }
+
+    /// Density on the left edge of the cell at `column` and `row`, `fy` of the way down it.
+    fn column_sample(&self, column: usize, row: usize, fy: f32) -> f32 {
+        lerp(self.value(column, row), self.value(column, row + 1), fy)
+    }
+
+    fn corner(&self, column: usize, row: usize) -> Vec2 {
+        self.origin + vec2(count_to_f32(column), count_to_f32(row)) * self.cell_size
+    }
+
+    /// Density at `point`, bilinearly interpolated, negative outside of the grid.
+    #[must_use]
+    pub fn sample(&self, point: Vec2) -> f32 {
+        let (column, fx) = self.locate(point.x, self.origin.x, self.columns);
+        let (row, fy) = self.locate(point.y, self.origin.y, self.rows);
+        let (Some(fx), Some(fy)) = (fx, fy) else {
+            return -1.0;
+        };
+        lerp(
+            self.column_sample(column, row, fy),
+            self.column_sample(column + 1, row, fy),
+            fx,
+        )
+    }
+
+    /// The density along the horizontal line at `y`, interpolated down to it once for all
+    /// the points sampled on it.
+    #[must_use]
+    pub fn row(&self, y: f32) -> DensityRow<'_> {
+        let (row, fy) = self.locate(y, self.origin.y, self.rows);
+        let columns = fy.map_or_else(Vec::new, |fy| {
+            (0..self.columns)
+                .map(|column| self.column_sample(column, row, fy))
+                .collect()
+        });
+        DensityRow {
+            field: self,
+            columns,
+        }
+    }
+
+    #[must_use]
+    pub fn is_solid(&self, point: Vec2) -> bool {
+        self.sample(point) > 0.0
+    }
+
+    /// Bounds of the solid terrain and its farthest distance from `center`.
+    #[must_use]
+    pub fn extents(&self, center: Vec2) -> (Rect, f32) {
+        let mut bounds: Option<Rect> = None;
+        let mut max_radius: f32 = 0.0;
+        for &segment in &self.contour {
+            for point in <[Vec2; 2]>::from(segment) {
+                let point_rect = Rect::new(point.x, point.y, 0.0, 0.0);
+                bounds = Some(bounds.map_or(point_rect, |rect| rect.combine_with(point_rect)));
+                max_radius = max_radius.max(point.distance(center));
+            }
+        }
+        (
+            bounds.unwrap_or_else(|| Rect::new(center.x, center.y, 0.0, 0.0)),
+            max_radius,
+        )
+    }
+
+    /// Carves out or fills in a circle, returns the area of the world that changed.
+    pub fn edit(&mut self, point: Vec2, radius: f32, edit: TerrainEdit) -> Option<Rect> {
+        let reach = radius + self.cell_size;
+        let reach_vector = vec2(reach, reach);
+        let far_corner = self.corner(self.columns - 1, self.rows - 1);
+        let outside = (point + reach_vector).cmplt(self.origin).any()
+            || (point - reach_vector).cmpgt(far_corner).any();
+        if outside {
+            return None;
+        }
+        let (first_column, first_row) = self.cell_at(point - reach_vector);
+        let (last_column, last_row) = self.cell_at(point + reach_vector);
+        for row in first_row..=last_row + 1 {
+            for column in first_column..=last_column + 1 {
+                // The circle as a density of its own, in the same units the field uses.
+                let circle = (radius - self.corner(column, row).distance(point)) / self.cell_size;
+                if let Some(value) = self.values.get_mut(row * self.columns + column) {
+                    *value = match edit {
+                        TerrainEdit::Dig => value.min(-circle),
+                        TerrainEdit::Build => value.max(circle),
+                    };
+                }
+            }
+        }
+        self.march();
+        Some(Rect::new(
+            point.x - reach,
+            point.y - reach,
+            reach * 2.0,
+            reach * 2.0,
+        ))
+    }
+
+    /// Sweeps a point moving from `from` to `to` against the contour. Only the cells
+    /// around the move are looked at, so short moves stay cheap in big fields.
+    #[must_use]
+    pub fn sweep(&self, from: Vec2, to: Vec2) -> Option<Contact> {
+        let (first_column, first_row) = self.cell_at(from.min(to));
+        let (last_column, last_row) = self.cell_at(from.max(to));
+        // A cell of slack either side, so moves along a cell border see both neighbours.
+        let columns = first_column.saturating_sub(1)..=(last_column + 1).min(self.columns - 2);
+        let rows = first_row.saturating_sub(1)..=(last_row + 1).min(self.rows - 2);
+        let cells_per_row = self.columns - 1;
+        let segments = rows.flat_map(|row| {
+            let first_cell = row * cells_per_row + columns.start();
+            let last_cell = row * cells_per_row + columns.end();
+            let start = self.cell_starts.get(first_cell).copied().unwrap_or(0);
+            let end = self
+                .cell_starts
+                .get(last_cell + 1)
+                .copied()
+                .unwrap_or(start);
+            self.contour
+                .get(start..end)
+                .unwrap_or_default()
+                .iter()
+                .copied()
+        });
+        collision::sweep(segments, from, to)
+    }
+
+    /// The contour joined up into polylines, closed ones end on the point they start from.
+    #[must_use]
+    pub fn contour_polylines(&self) -> Vec<Vec<Vec2>> {
+        // Neighbouring cells compute the crossing on their shared edge the same way,
+        // so segments that join share their end points exactly.
+        let key = |point: Vec2| (point.x.to_bits(), point.y.to_bits());
+        let mut ends: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+        for (index, &(start, end)) in self.contour.iter().enumerate() {
+            ends.entry(key(start)).or_default().push(index);
+            ends.entry(key(end)).or_default().push(index);
+        }
+        let mut used = vec![false; self.contour.len()];
+        // Marks a segment as used, `false` if it already was.
+        let claim = |used: &mut [bool], index: usize| {
+            used.get_mut(index)
+                .is_some_and(|used| !std::mem::replace(used, true))
+        };
+        let mut polylines = Vec::new();
+        for (first, &(start, end)) in self.contour.iter().enumerate() {
+            if !claim(&mut used, first) {
+                continue;
+            }
+            let mut polyline = vec![start, end];
+            // Follow the line forwards from the end, then backwards from the start.
+            for backwards in [false, true] {
+                loop {
+                    let tip = if backwards {
+                        polyline.first()
+                    } else {
+                        polyline.last()
+                    };
+                    let Some(tip) = tip.copied() else {
+                        break;
+                    };
+                    let next = ends.get(&key(tip)).and_then(|indices| {
+                        indices
+                            .iter()
+                            .copied()
+                            .find(|&index| claim(&mut used, index))
+                    });
+                    let Some(&(start, end)) = next.and_then(|index| self.contour.get(index)) else {
+                        break;
+                    };
+                    let point = if key(start) == key(tip) { end } else { start };
+                    if backwards {
+                        polyline.insert(0, point);
+                    } else {
+                        polyline.push(point);
+                    }
+                }
+            }
+            polylines.push(polyline);
+        }
+        polylines
+    }
+
+    /// Rebuilds `contour` from the density values.
+    fn march(&mut self) {
+        let mut contour = Vec::new();
+        let mut cell_starts = Vec::with_capacity((self.rows - 1) * (self.columns - 1) + 1);
+        for row in 0..self.rows - 1 {
+            for column in 0..self.columns - 1 {
+                cell_starts.push(contour.len());
+                self.march_cell(column, row, &mut contour);
+            }
+        }
+        cell_starts.push(contour.len());
+        self.contour = contour;
+        self.cell_starts = cell_starts;
+    }
+
+    fn march_cell(&self, column: usize, row: usize, contour: &mut Vec<(Vec2, Vec2)>) {
+        let corners = [
+            (column, row),
+            (column + 1, row),
+            (column + 1, row + 1),
+            (column, row + 1),
+        ];
+        let samples =
+            corners.map(|(column, row)| (self.value(column, row), self.corner(column, row)));
+        let case = samples.iter().fold(0, |case, &(value, _)| {
+            (case << 1) | usize::from(value > 0.0)
+        });
+        // Where the boundary crosses the edge between two corners.
+        let edge = |(from_value, from): (f32, Vec2), (to_value, to): (f32, Vec2)| {
+            from.lerp(to, from_value / (from_value - to_value))
+        };
+        let [top_left, top_right, bottom_right, bottom_left] = samples;
+        let top = edge(top_left, top_right);
+        let right = edge(top_right, bottom_right);
+        let bottom = edge(bottom_left, bottom_right);
+        let left = edge(top_left, bottom_left);
+        let center_solid = samples.iter().map(|&(value, _)| value).sum::<f32>() > 0.0;
+        // Bits are top left, top right, bottom right, bottom left.
+        match case {
+            0b0001 | 0b1110 => contour.push((left, bottom)),
+            0b0010 | 0b1101 => contour.push((bottom, right)),
+            0b0011 | 0b1100 => contour.push((left, right)),
+            0b0100 | 0b1011 => contour.push((top, right)),
+            0b0110 | 0b1001 => contour.push((top, bottom)),
+            0b0111 | 0b1000 => contour.push((left, top)),
+            // Saddles, the average of the corners decides which diagonal is connected.
+            0b0101 if center_solid => contour.extend([(left, top), (bottom, right)]),
+            0b0101 => contour.extend([(left, bottom), (top, right)]),
+            0b1010 if center_solid => contour.extend([(top, right), (left, bottom)]),
+            0b1010 => contour.extend([(left, top), (bottom, right)]),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single cell from the origin to `(1, 1)`, with the corner values clockwise from the top left.
+    fn cell(corners: [f32; 4]) -> DensityField {
+        let [top_left, top_right, bottom_right, bottom_left] = corners;
+        let values = vec![top_left, top_right, bottom_left, bottom_right];
+        DensityField::from_values(vec2(0.0, 0.0), 1.0, 2, values).expect("a cell is a valid grid")
+    }
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        a.distance(b) < 1e-5
+    }
+
+    #[test]
+    fn marches_every_case_between_the_solid_and_empty_corners() {
+        let corners = [
+            vec2(0.0, 0.0),
+            vec2(1.0, 0.0),
+            vec2(1.0, 1.0),
+            vec2(0.0, 1.0),
+        ];
+        for case in 0..16_u8 {
+            // Bits are top left, top right, bottom right, bottom left, like `march_cell`.
+            let solid = [8, 4, 2, 1].map(|bit| case & bit != 0);
+            let field = cell(solid.map(|solid| if solid { 1.0 } else { -1.0 }));
+            // With opposite values on either end, the boundary crosses edges halfway.
+            let crossings: Vec<Vec2> = (0..4)
+                .zip(1..=4)
+                .filter_map(|(from, to)| {
+                    let (from, to) = (from % 4, to % 4);
+                    let differs = solid.get(from) != solid.get(to);
+                    let (&start, &end) = (corners.get(from)?, corners.get(to)?);
+                    differs.then(|| start.lerp(end, 0.5))
+                })
+                .collect();
+            let ends: Vec<Vec2> = field
+                .contour
+                .iter()
+                .flat_map(|&segment| <[Vec2; 2]>::from(segment))
+                .collect();
+            assert_eq!(ends.len(), crossings.len(), "case {case:04b}");
+            for crossing in crossings {
+                let uses = ends.iter().filter(|&&end| close(end, crossing)).count();
+                assert_eq!(uses, 1, "case {case:04b} should cross {crossing} once");
+            }
+            for &(start, end) in &field.contour {
+                assert!(!close(start, end), "case {case:04b} has an empty segment");
+            }
+        }
+    }
+
+    #[test]
+    fn saddles_connect_the_diagonal_the_average_favours() {
+        let has = |field: &DensityField, a: Vec2, b: Vec2| {
+            field.contour.iter().any(|&(start, end)| {
+                (close(start, a) && close(end, b)) || (close(start, b) && close(end, a))
+            })
+        };
+        // One diagonal is three times as far from zero as the other, so the boundary
+        // crosses each edge a quarter of the way from the weaker corner.
+        // Top right and bottom left solid.
+        let (top, right, bottom, left) = (
+            vec2(0.25, 0.0),
+            vec2(1.0, 0.75),
+            vec2(0.75, 1.0),
+            vec2(0.0, 0.25),
+        );
+        let connected = cell([-1.0, 3.0, -1.0, 3.0]);
+        assert!(connected.is_solid(vec2(0.5, 0.5)));
+        assert_eq!(connected.contour.len(), 2);
+        assert!(has(&connected, left, top) && has(&connected, bottom, right));
+        let (top, right, bottom, left) = (
+            vec2(0.75, 0.0),
+            vec2(1.0, 0.25),
+            vec2(0.25, 1.0),
+            vec2(0.0, 0.75),
+        );
+        let separate = cell([-3.0, 1.0, -3.0, 1.0]);
+        assert!(!separate.is_solid(vec2(0.5, 0.5)));
+        assert_eq!(separate.contour.len(), 2);
+        assert!(has(&separate, left, bottom) && has(&separate, top, right));
+        // Top left and bottom right solid.
+        let (top, right, bottom, left) = (
+            vec2(0.75, 0.0),
+            vec2(1.0, 0.25),
+            vec2(0.25, 1.0),
+            vec2(0.0, 0.75),
+        );
+        let connected = cell([3.0, -1.0, 3.0, -1.0]);
+        assert!(connected.is_solid(vec2(0.5, 0.5)));
+        assert!(has(&connected, top, right) && has(&connected, left, bottom));
+        let (top, right, bottom, left) = (
+            vec2(0.25, 0.0),
+            vec2(1.0, 0.75),
+            vec2(0.75, 1.0),
+            vec2(0.0, 0.25),
+        );
+        let separate = cell([1.0, -3.0, 1.0, -3.0]);
+        assert!(!separate.is_solid(vec2(0.5, 0.5)));
+        assert!(has(&separate, left, top) && has(&separate, bottom, right));
+    }
+
+    #[test]
+    fn edits_change_what_is_solid() {
+        let bounds = Rect::new(0.0, 0.0, 100.0, 100.0);
+        let mut field = DensityField::new(bounds, 4.0, |_| 1.0);
+        assert!(field.contour.is_empty());
+        assert!(field.is_solid(vec2(50.0, 50.0)));
+        assert!(!field.is_solid(vec2(-1.0, 50.0)));
+        assert!(field
+            .edit(vec2(50.0, 50.0), 20.0, TerrainEdit::Dig)
+            .is_some());
+        assert!(!field.is_solid(vec2(50.0, 50.0)));
+        assert!(!field.is_solid(vec2(50.0, 65.0)));
+        assert!(field.is_solid(vec2(50.0, 75.0)));
+        assert!(!field.contour.is_empty());
+        assert!(field
+            .edit(vec2(50.0, 50.0), 10.0, TerrainEdit::Build)
+            .is_some());
+        assert!(field.is_solid(vec2(50.0, 50.0)));
+        assert!(!field.is_solid(vec2(50.0, 65.0)));
+        assert_eq!(field.edit(vec2(500.0, 50.0), 10.0, TerrainEdit::Dig), None);
+    }
+
+    #[test]
+    fn sweeps_find_what_checking_every_segment_finds() {
+        let bounds = Rect::new(-100.0, -100.0, 200.0, 200.0);
+        let mut field = DensityField::new(bounds, 8.0, |point| 60.0 - point.length());
+        field.edit(vec2(40.0, 20.0), 25.0, TerrainEdit::Dig);
+        for step in 0..360_u16 {
+            let angle = f32::from(step).to_radians();
+            let direction = vec2(angle.sin(), angle.cos());
+            for (from, to) in [
+                (direction * 120.0, direction * 20.0),
+                (direction * 55.0, direction * 65.0),
+                (
+                    direction * 70.0,
+                    direction * 70.0 + vec2(direction.y, -direction.x) * 30.0,
+                ),
+                (direction * 150.0, -direction * 150.0),
+            ] {
+                let everything = collision::sweep(field.contour.iter().copied(), from, to);
+                assert_eq!(field.sweep(from, to), everything, "{from} to {to}");
+            }
+        }
+        assert!(field.sweep(vec2(0.0, 200.0), vec2(0.0, 0.0)).is_some());
+    }
+
+    #[test]
+    fn rows_sample_what_the_field_samples() {
+        let bounds = Rect::new(-50.0, -50.0, 100.0, 100.0);
+        let mut field = DensityField::new(bounds, 8.0, |point| 30.0 - point.length());
+        field.edit(vec2(10.0, 0.0), 12.0, TerrainEdit::Dig);
+        for y in -60..60_i16 {
+            let y = f32::from(y) + 0.5;
+            let row = field.row(y);
+            for x in -60..60_i16 {
+                let x = f32::from(x) + 0.25;
+                let sample = field.sample(vec2(x, y));
+                assert_eq!(row.sample(x).to_bits(), sample.to_bits(), "at ({x}, {y})");
+                // A cell counted as solid throughout is, up to the last one of its run.
+                let solid_run = row.cell(x).and_then(|column| row.solid_through(column));
+                if let Some(last) = solid_run {
+                    assert!(sample > 0.0, "({x}, {y}) should be solid");
+                    assert!(row.cell(x).is_some_and(|column| column <= last));
+                }
+            }
+        }
+        assert_eq!(field.row(-70.0).cell(0.0), None);
+        assert_eq!(field.row(0.0).cell(-70.0), None);
+    }
+}
@@ -0,0 +1,210 @@
+use macroquad::prelude::*;
+use serde::Serialize;
+
+use crate::planet::Planet;
+
+/// What goes into an SVG besides the outline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SvgOptions {
+    /// Fill the ground, and the ocean around it.
+    pub fill: bool,
+    /// Draw the interior layers where the ground is.
+    pub strata: bool,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            fill: true,
+            strata: true,
+        }
+    }
+}
+
+/// The planet as an SVG drawing, in world units with `y` up like the game shows it.
+#[must_use]
+pub fn to_svg(planet: &Planet, options: SvgOptions) -> String {
+    let Rect { x, y, w, h } = planet.image_extents();
+    let outline = svg_path(&planet.outline());
+    let palette = &planet.palette;
+    let (cx, cy) = (planet.center.x, planet.center.y);
+    let mut svg = vec![
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{x:.2} {:.2} {w:.2} {h:.2}" width="{w:.0}" height="{h:.0}">"#,
+            -(y + h)
+        ),
+        String::from(r#"<g transform="scale(1 -1)">"#),
+    ];
+    if options.strata {
+        svg.push(format!(
+            r#"<defs><clipPath id="ground-clip"><path clip-rule="evenodd" d="{outline}"/></clipPath></defs>"#
+        ));
+    }
+    if options.fill {
+        if let Some(sea_radius) = planet.sea_radius() {
+            svg.push(format!(
+                r#"<circle id="ocean" cx="{cx:.2}" cy="{cy:.2}" r="{sea_radius:.2}" fill="{}"/>"#,
+                hex(palette.water)
+            ));
+        }
+        svg.push(format!(
+            r#"<path id="ground" fill-rule="evenodd" fill="{}" d="{outline}"/>"#,
+            hex(palette.ground_color(0.0, 0.0, 0.0))
+        ));
+    }
+    if options.strata {
+        svg.push(String::from(
+            r#"<g id="strata" clip-path="url(#ground-clip)">"#,
+        ));
+        for stratum in &palette.interior {
+            svg.push(format!(
+                r#"<circle id="{}" cx="{cx:.2}" cy="{cy:.2}" r="{:.2}" fill="{}"/>"#,
+                stratum.name.replace(' ', "-"),
+                planet.radius() * (1.0 - stratum.depth),
+                hex(stratum.color)
+            ));
+        }
+        svg.push(String::from("</g>"));
+    }
+    svg.push(format!(
+        r##"<path id="outline" fill="none" stroke="#7a7a7a" stroke-width="4" d="{outline}"/>"##
+    ));
+    svg.push(String::from("</g>"));
+    svg.push(String::from("</svg>\n"));
+    svg.join("\n")
+}
+
+/// The outline as CSV, one point per row, numbered by the polyline it belongs to.
+/// Closed polylines don't repeat their first point, the radial surface is a row per sample.
+#[must_use]
+pub fn to_csv(planet: &Planet) -> String {
+    let mut rows = vec![String::from("polyline,x,y")];
+    for (index, polyline) in planet.outline().iter().enumerate() {
+        let closed = polyline.len() > 1 && polyline.first() == polyline.last();
+        let points = polyline.len() - usize::from(closed);
+        rows.extend(
+            polyline
+                .iter()
+                .take(points)
+                .map(|point| format!("{index},{},{}", point.x, point.y)),
+        );
+    }
+    rows.push(String::new());
+    rows.join("\n")
+}
+
+/// What `to_json` writes, points are `[x, y]` pairs.
+#[derive(Serialize)]
+struct JsonOutline {
+    center: [f32; 2],
+    radius: f32,
+    sea_radius: Option<f32>,
+    polylines: Vec<Vec<[f32; 2]>>,
+}
+
+/// The outline as JSON, with what else an engine needs to place the planet.
+/// Numbers that aren't finite come out as `null`.
+#[must_use]
+pub fn to_json(planet: &Planet) -> String {
+    let outline = JsonOutline {
+        center: planet.center.into(),
+        radius: planet.radius(),
+        sea_radius: planet.sea_radius(),
+        polylines: planet
+            .outline()
+            .iter()
+            .map(|polyline| polyline.iter().map(|&point| point.into()).collect())
+            .collect(),
+    };
+    let mut json = serde_json::to_string(&outline).expect("numbers and lists always serialize");
+    json.push('\n');
+    json
+}
+
+fn svg_path(polylines: &[Vec<Vec2>]) -> String {
+    let mut path = Vec::new();
+    for polyline in polylines {
+        let (Some(first), Some(last)) = (polyline.first(), polyline.last()) else {
+            continue;
+        };
+        path.push(format!("M{:.2},{:.2}", first.x, first.y));
+        path.extend(
+            polyline
+                .iter()
+                .skip(1)
+                .map(|point| format!("L{:.2},{:.2}", point.x, point.y)),
+        );
+        if first == last {
+            path.push(String::from("Z"));
+        }
+    }
+    path.concat()
+}
+
+fn hex(color: Color) -> String {
+    let [r, g, b, _]: [u8; 4] = color.into();
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::{Noise, NoiseSettings};
+
+    fn planet(caves: bool) -> Planet {
+        let noise = Noise::new(5, NoiseSettings::default()).expect("default noise should be valid");
+        let mut planet = Planet::new(vec2(100.0, -50.0), 60.0, 96, &noise);
+        if caves {
+            planet.carve_caves(&noise);
+        }
+        planet
+    }
+
+    #[test]
+    fn svg_paths_are_closed() {
+        for caves in [false, true] {
+            let path = svg_path(&planet(caves).outline());
+            assert!(path.starts_with('M'), "{path}");
+            assert!(path.ends_with('Z'), "{path}");
+        }
+        let svg = to_svg(&planet(false), SvgOptions::default());
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn csv_has_a_row_per_surface_sample() {
+        let planet = planet(false);
+        let csv = to_csv(&planet);
+        let mut rows = csv.lines();
+        assert_eq!(rows.next(), Some("polyline,x,y"));
+        let rows: Vec<&str> = rows.collect();
+        assert_eq!(rows.len(), planet.surface.len());
+        for (row, point) in rows.iter().zip(&planet.surface) {
+            assert_eq!(*row, format!("0,{},{}", point.x, point.y));
+        }
+    }
+
+    #[test]
+    fn json_parses_back() {
+        let planet = planet(true);
+        let json: serde_json::Value =
+            serde_json::from_str(&to_json(&planet)).expect("the export should be valid JSON");
+        let radius = json.get("radius").and_then(serde_json::Value::as_f64);
+        assert_eq!(radius, Some(f64::from(planet.radius())));
+        let polylines = json
+            .get("polylines")
+            .and_then(serde_json::Value::as_array)
+            .expect("polylines should be an array");
+        assert_eq!(polylines.len(), planet.outline().len());
+    }
+
+    #[test]
+    fn json_stays_valid_with_numbers_that_are_not_finite() {
+        let mut planet = planet(false);
+        planet.sea_level = Some(f32::NAN);
+        let json: serde_json::Value =
+            serde_json::from_str(&to_json(&planet)).expect("the export should be valid JSON");
+        assert_eq!(json.get("sea_radius"), Some(&serde_json::Value::Null));
+    }
+}
@@ -0,0 +1,181 @@
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use macroquad::prelude::*;
+
+use crate::planet::Planet;
+
+/// What the jobs queued on a worker produced: the planet, and its image if one was asked for.
+pub struct Generated {
+    pub planet: Planet,
+    pub image: Option<Image>,
+    /// How many queued jobs went into the planet.
+    applied: usize,
+}
+
+/// Where the jobs queued on a worker are at.
+pub enum Progress {
+    /// No jobs are queued.
+    Idle,
+    Running,
+    /// Every queued job is done, this is the planet the last one produced.
+    Done(Box<Generated>),
+    /// A job panicked, the worker and the jobs queued after it are gone.
+    Failed,
+}
+
+struct Job {
+    /// The planet to start from, `None` to carry on from the planet the previous job produced.
+    base: Option<Planet>,
+    render: bool,
+    work: Box<dyn FnOnce(Planet) -> Planet + Send>,
+}
+
+/// A worker thread reshaping or rendering a planet, so the frame loop keeps running.
+/// Jobs queue up on the one thread and apply in order, each to the planet of the one before.
+pub struct PlanetWorker {
+    jobs: Sender<Job>,
+    results: Receiver<Generated>,
+    /// Jobs sent but not reported back yet.
+    queued: usize,
+    /// Whether any of the queued jobs asked for an image.
+    render: bool,
+}
+
+impl PlanetWorker {
+    pub fn spawn() -> Self {
+        let (jobs, inbox) = mpsc::channel::<Job>();
+        let (outbox, results) = mpsc::channel();
+        thread::spawn(move || {
+            let mut current: Option<Planet> = None;
+            while let Ok(job) = inbox.recv() {
+                // Jobs queued in the meantime are applied before rendering, only the last
+                // planet of the batch is worth an image.
+                let (mut applied, mut render) = (0, false);
+                let mut next = Some(job);
+                while let Some(Job {
+                    base,
+                    render: wanted,
+                    work,
+                }) = next
+                {
+                    let Some(planet) = base.or_else(|| current.take()) else {
+                        return;
+                    };
+                    current = Some(work(planet));
+                    (applied, render) = (applied + 1, wanted);
+                    next = inbox.try_recv().ok();
+                }
+                let Some(planet) = current.clone() else {
+                    return;
+                };
+                let image = render.then(|| Planet::as_image(&planet)).flatten();
+                let generated = Generated {
+                    planet,
+                    image,
+                    applied,
+                };
+                if outbox.send(generated).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            jobs,
+            results,
+            queued: 0,
+            render: false,
+        }
+    }
+
+    pub const fn is_busy(&self) -> bool {
+        self.queued > 0
+    }
+
+    /// Queues `work`, rendering the result when `render` is set. It starts from `base` when
+    /// nothing is queued, and from the planet the previous job produced otherwise.
+    pub fn start(
+        &mut self,
+        base: &Planet,
+        render: bool,
+        work: impl FnOnce(Planet) -> Planet + Send + 'static,
+    ) {
+        // An image asked for by an earlier job is still wanted once later ones are done.
+        self.render |= render;
+        let job = Job {
+            base: (!self.is_busy()).then(|| base.clone()),
+            render: self.render,
+            work: Box::new(work),
+        };
+        // A worker that is gone shows up as `Progress::Failed` when finishing.
+        self.jobs.send(job).ok();
+        self.queued += 1;
+    }
+
+    /// Collects what the worker produced, blocking until every queued job is done if `block`
+    /// is set. Planets the worker reports before the last job is done are superseded.
+    pub fn finish(&mut self, block: bool) -> Progress {
+        let mut latest = None;
+        while self.is_busy() {
+            let received = if block {
+                self.results.recv().map_err(|_| TryRecvError::Disconnected)
+            } else {
+                self.results.try_recv()
+            };
+            match received {
+                Ok(generated) => {
+                    self.queued = self.queued.saturating_sub(generated.applied);
+                    latest = Some(generated);
+                }
+                Err(TryRecvError::Empty) => return Progress::Running,
+                Err(TryRecvError::Disconnected) => return Progress::Failed,
+            }
+        }
+        self.render = false;
+        latest.map_or(Progress::Idle, |generated| {
+            Progress::Done(Box::new(generated))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::{Noise, NoiseSettings};
+
+    #[test]
+    fn queued_jobs_apply_in_order_on_one_worker() {
+        let noise = Noise::new(3, NoiseSettings::default()).expect("default noise should be valid");
+        let planet = Planet::new(Vec2::ZERO, 40.0, 64, &noise);
+        let mut worker = PlanetWorker::spawn();
+        worker.start(&planet, true, |mut planet| {
+            planet.sea_level = Some(1.0);
+            planet
+        });
+        // Started from the planet the first job produces, not from this base.
+        worker.start(&planet, false, |mut planet| {
+            planet.sea_level = planet.sea_level.map(|level| level + 2.0);
+            planet
+        });
+        assert!(worker.is_busy());
+        let Progress::Done(generated) = worker.finish(true) else {
+            panic!("the jobs should be done");
+        };
+        assert_eq!(generated.planet.sea_level, Some(3.0));
+        assert!(
+            generated.image.is_some(),
+            "the first job asked for an image"
+        );
+        assert!(!worker.is_busy());
+        assert!(matches!(worker.finish(false), Progress::Idle));
+    }
+
+    #[test]
+    fn a_panicking_job_fails_the_worker() {
+        let noise = Noise::new(3, NoiseSettings::default()).expect("default noise should be valid");
+        let planet = Planet::new(Vec2::ZERO, 40.0, 64, &noise);
+        let mut worker = PlanetWorker::spawn();
+        worker.start(&planet, false, |_| panic!("the job fails"));
+        assert!(matches!(worker.finish(true), Progress::Failed));
+    }
+}
@@ -0,0 +1,83 @@
+//! Planets shaped from noise, the game played on them, and the tools around it.
+#![warn(
+    clippy::pedantic,
+    clippy::nursery,
+    clippy::cargo,
+    clippy::unwrap_used,
+    clippy::unwrap_in_result,
+    clippy::unneeded_field_pattern,
+    clippy::string_to_string,
+    clippy::string_slice,
+    clippy::string_add,
+    clippy::str_to_string,
+    clippy::same_name_method,
+    clippy::rest_pat_in_fully_bound_structs,
+    clippy::rc_mutex,
+    clippy::rc_buffer,
+    clippy::pattern_type_mismatch,
+    clippy::multiple_inherent_impl,
+    clippy::missing_enforced_import_renames,
+    clippy::lossy_float_literal,
+    clippy::let_underscore_must_use,
+    clippy::integer_division,
+    clippy::inline_asm_x86_att_syntax,
+    clippy::indexing_slicing,
+    clippy::if_then_some_else_none,
+    clippy::get_unwrap,
+    clippy::fn_to_numeric_cast,
+    clippy::float_cmp_const,
+    clippy::filetype_is_file,
+    clippy::create_dir,
+    clippy::clone_on_ref_ptr,
+    clippy::as_conversions,
+    clippy::verbose_file_reads
+)]
+#![allow(clippy::wildcard_imports, unused_imports)]
+
+mod bench;
+mod camera;
+mod cli;
+mod collision;
+mod common;
+mod controls;
+mod density;
+mod export;
+mod job;
+mod mesh;
+mod noise;
+mod palette;
+mod planet;
+mod player;
+mod save;
+mod world;
+use world::World;
+
+use macroquad::window::next_frame;
+
+pub use bench::rasterization;
+pub use cli::{generate, CliError};
+
+/// The game loop, run in the window `main` opens.
+pub async fn game() {
+    let mut world = World::new(::rand::random::<u64>());
+    let controls_path = std::path::Path::new(controls::CONTROLS_PATH);
+    if controls_path.exists() {
+        match controls::InputMap::load(controls_path) {
+            Ok(controls) => world.set_controls(controls),
+            Err(error) => macroquad::logging::error!(
+                "couldn't read {}, using the default controls: {}",
+                controls::CONTROLS_PATH,
+                error
+            ),
+        }
+    }
+    world.setup();
+
+    loop {
+        world.input();
+        world.update();
+        world.draw();
+
+        next_frame().await;
+    }
+}
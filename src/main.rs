@@ -1,58 +1,9 @@
-#![warn(
-    clippy::pedantic,
-    clippy::nursery,
-    clippy::cargo,
-    clippy::unwrap_used,
-    clippy::unwrap_in_result,
-    clippy::unneeded_field_pattern,
-    clippy::string_to_string,
-    clippy::string_slice,
-    clippy::string_add,
-    clippy::str_to_string,
-    clippy::same_name_method,
-    clippy::rest_pat_in_fully_bound_structs,
-    clippy::rc_mutex,
-    clippy::rc_buffer,
-    clippy::pattern_type_mismatch,
-    clippy::multiple_inherent_impl,
-    clippy::missing_enforced_import_renames,
-    clippy::lossy_float_literal,
-    clippy::let_underscore_must_use,
-    clippy::integer_division,
-    clippy::inline_asm_x86_att_syntax,
-    clippy::indexing_slicing,
-    clippy::if_then_some_else_none,
-    clippy::get_unwrap,
-    clippy::fn_to_numeric_cast,
-    clippy::float_cmp_const,
-    clippy::filetype_is_file,
-    clippy::create_dir,
-    clippy::clone_on_ref_ptr,
-    clippy::as_conversions,
-    clippy::verbose_file_reads
-)]
-#![allow(clippy::wildcard_imports, unused_imports)]
-
-mod camera;
-mod common;
-mod noise;
-mod planet;
-mod player;
-mod world;
-use world::World;
-
-use macroquad::window::next_frame;
-
-#[macroquad::main("game")]
-async fn main() {
-    let mut world = World::new();
-    world.setup();
-
-    loop {
-        world.input();
-        world.update();
-        world.draw();
-
-        next_frame().await;
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("bench") => {
+            planet_shaper::rasterization(args.get(1).and_then(|seed| seed.parse().ok()));
+        }
+        _ => macroquad::Window::new("game", planet_shaper::game()),
     }
 }
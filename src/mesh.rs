@@ -0,0 +1,158 @@
+use macroquad::models::{Mesh, Vertex};
+use macroquad::prelude::*;
+
+use crate::planet::Planet;
+
+/// Surface segments in one mesh. macroquad draws at most 5000 indices in a single call
+/// and every segment takes nine.
+const CHUNK_SEGMENTS: usize = 512;
+/// World units one repeat of a tiling ground texture covers.
+const TEXTURE_SCALE: f32 = 256.0;
+
+/// The planet's radial surface as triangles: a strip of ground from the surface down
+/// to the base radius, and a fan from there in to the center. Ground is colored by
+/// altitude, the fan fades from the crust to the core.
+///
+/// Split into meshes small enough to draw in one call each, all triangles wind
+/// counter-clockwise in world space. Empty for planets with caves, which aren't radial.
+#[must_use]
+pub fn planet_mesh(planet: &Planet) -> Vec<Mesh> {
+    let segments = planet.surface.len();
+    if planet.has_caves() || segments < 2 {
+        return Vec::new();
+    }
+    (0..segments)
+        .step_by(CHUNK_SEGMENTS)
+        .map(|first| chunk(planet, first, CHUNK_SEGMENTS.min(segments - first)))
+        .collect()
+}
+
+/// The mesh of `count` segments starting at surface point `first`. The center comes first,
+/// then each surface point followed by the point under it on the base radius.
+fn chunk(planet: &Planet, first: usize, count: usize) -> Mesh {
+    let palette = &planet.palette;
+    let mut vertices = Vec::with_capacity(2 * count + 3);
+    vertices.push(vertex(planet.center, palette.interior_color(1.0)));
+    let points = planet.surface.iter().enumerate().cycle().skip(first);
+    for (index, &point) in points.take(count + 1) {
+        let distance = point.distance(planet.center);
+        // Ground dug below the base radius has no strip under it, it folds flat there.
+        let inner = if distance > planet.radius() {
+            planet.center + (point - planet.center) * (planet.radius() / distance)
+        } else {
+            point
+        };
+        let depth = (planet.radius() - distance).max(0.0) / planet.radius();
+        vertices.push(vertex(point, planet.surface_color(index)));
+        vertices.push(vertex(inner, palette.interior_color(depth)));
+    }
+    let mut indices = Vec::with_capacity(9 * count);
+    for segment in 0..count {
+        let outer = u16::try_from(2 * segment + 1).expect("chunks should fit u16 indices");
+        let (inner, next_outer, next_inner) = (outer + 1, outer + 2, outer + 3);
+        // Surface points go clockwise, so triangles list the next point first.
+        indices.extend([0, next_inner, inner]);
+        indices.extend([outer, inner, next_inner]);
+        indices.extend([outer, next_inner, next_outer]);
+    }
+    Mesh {
+        vertices,
+        indices,
+        texture: None,
+    }
+}
+
+fn vertex(position: Vec2, color: Color) -> Vertex {
+    Vertex {
+        position: position.extend(0.0),
+        uv: position / TEXTURE_SCALE,
+        color,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::{Noise, NoiseSettings};
+
+    /// A round planet whose surface is a bit above its base radius, so no triangle folds flat.
+    fn planet(resolution: u16) -> Planet {
+        let noise = Noise::new(1, NoiseSettings::default()).expect("default noise should be valid");
+        let center = vec2(300.0, -100.0);
+        let surface = (0..resolution)
+            .map(|point| {
+                let angle = f32::from(point) * std::f32::consts::TAU / f32::from(resolution);
+                center + vec2(angle.sin(), angle.cos()) * 120.0
+            })
+            .collect();
+        Planet::from_surface(center, 100.0, surface, &noise)
+    }
+
+    fn corners(mesh: &Mesh) -> Vec<[Vec2; 3]> {
+        let position = |index: u16| {
+            mesh.vertices
+                .get(usize::from(index))
+                .expect("indices should point at vertices")
+                .position
+                .truncate()
+        };
+        mesh.indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = <[u16; 3]>::try_from(triangle).expect("chunks of three");
+                [position(a), position(b), position(c)]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chunks_have_a_strip_and_a_fan_per_segment() {
+        let meshes = planet_mesh(&planet(100));
+        assert_eq!(meshes.len(), 1, "one chunk should do");
+        let mesh = meshes.first().expect("there should be a chunk");
+        assert_eq!(mesh.vertices.len(), 2 * 100 + 3);
+        assert_eq!(mesh.indices.len(), 9 * 100);
+    }
+
+    #[test]
+    fn triangles_wind_counter_clockwise() {
+        for mesh in planet_mesh(&planet(100)) {
+            for [a, b, c] in corners(&mesh) {
+                assert!((b - a).perp_dot(c - a) > 0.0, "{a} {b} {c}");
+            }
+        }
+    }
+
+    #[test]
+    fn big_planets_split_into_chunks_without_gaps() {
+        let planet = planet(1100);
+        let meshes = planet_mesh(&planet);
+        assert_eq!(meshes.len(), 3);
+        let segments: usize = meshes
+            .iter()
+            .map(|mesh| mesh.indices.len().div_euclid(9))
+            .sum();
+        assert_eq!(segments, planet.surface.len());
+        // Each chunk starts on the surface point the one before ends on.
+        let surface = |mesh: &Mesh, vertex: usize| {
+            mesh.vertices
+                .get(vertex)
+                .expect("chunks should have surface vertices")
+                .position
+                .truncate()
+        };
+        for (mesh, next) in meshes.iter().zip(meshes.iter().cycle().skip(1)) {
+            let last = mesh.vertices.len() - 2;
+            assert_eq!(surface(mesh, last), surface(next, 1));
+        }
+    }
+
+    #[test]
+    fn texture_coordinates_follow_the_world() {
+        for mesh in planet_mesh(&planet(100)) {
+            for vertex in &mesh.vertices {
+                assert_eq!(vertex.uv * TEXTURE_SCALE, vertex.position.truncate());
+            }
+        }
+    }
+}
@@ -1,44 +1,220 @@
 use crate::world::NOISE_SIZE;
 use macroquad::prelude::*;
 use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
 use simple_simplex::NoiseConfig;
 
-use ::rand::prelude::random;
+/// Parameters of the fractal simplex noise the planet surface is shaped by.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NoiseSettings {
+    pub octaves: i32,
+    pub x_frequency: f32,
+    pub y_frequency: f32,
+    pub amplitude: f32,
+    pub lacunarity: f32,
+    pub gain: f32,
+    /// Range the noise is mapped into, the first value is where the noise peaks.
+    pub range: (f32, f32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseSettingsError {
+    Octaves,
+    Frequency,
+    Amplitude,
+    Lacunarity,
+    Gain,
+    Range,
+}
+
+impl std::fmt::Display for NoiseSettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match *self {
+            Self::Octaves => "octaves should be between 1 and 16",
+            Self::Frequency => "frequencies should be positive",
+            Self::Amplitude => "amplitude should be positive",
+            Self::Lacunarity => "lacunarity should be at least 1",
+            Self::Gain => "gain should be in (0, 1]",
+            Self::Range => "range should consist of two different finite values",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for NoiseSettingsError {}
+
+impl NoiseSettings {
+    pub const ROCKY: Self = Self {
+        octaves: 4,
+        x_frequency: 0.008,
+        y_frequency: 0.008,
+        amplitude: 0.05,
+        lacunarity: 3.0,
+        gain: 0.25,
+        range: (0.0, 1.0),
+    };
+
+    pub const SMOOTH: Self = Self {
+        octaves: 2,
+        x_frequency: 0.004,
+        y_frequency: 0.004,
+        amplitude: 0.05,
+        lacunarity: 2.0,
+        gain: 0.3,
+        range: (0.0, 1.0),
+    };
+
+    pub const JAGGED: Self = Self {
+        octaves: 6,
+        x_frequency: 0.012,
+        y_frequency: 0.012,
+        amplitude: 0.05,
+        lacunarity: 2.5,
+        gain: 0.55,
+        range: (0.0, 1.0),
+    };
+
+    pub const PRESETS: [(&'static str, Self); 3] = [
+        ("rocky", Self::ROCKY),
+        ("smooth", Self::SMOOTH),
+        ("jagged", Self::JAGGED),
+    ];
+
+    #[must_use]
+    pub fn preset(name: &str) -> Option<Self> {
+        Self::PRESETS
+            .iter()
+            .find(|&&(preset_name, _)| preset_name == name)
+            .map(|&(_, preset)| preset)
+    }
+
+    #[must_use]
+    pub fn preset_name(&self) -> Option<&'static str> {
+        Self::PRESETS
+            .iter()
+            .find(|&&(_, preset)| preset == *self)
+            .map(|&(name, _)| name)
+    }
+
+    pub fn validate(&self) -> Result<(), NoiseSettingsError> {
+        let positive = |value: f32| value.is_finite() && value > 0.0;
+        if !(1..=16).contains(&self.octaves) {
+            return Err(NoiseSettingsError::Octaves);
+        }
+        if !positive(self.x_frequency) || !positive(self.y_frequency) {
+            return Err(NoiseSettingsError::Frequency);
+        }
+        if !positive(self.amplitude) {
+            return Err(NoiseSettingsError::Amplitude);
+        }
+        if !self.lacunarity.is_finite() || self.lacunarity < 1.0 {
+            return Err(NoiseSettingsError::Lacunarity);
+        }
+        if !positive(self.gain) || self.gain > 1.0 {
+            return Err(NoiseSettingsError::Gain);
+        }
+        let (from, to) = self.range;
+        if !from.is_finite() || !to.is_finite() || (from - to).abs() < f32::EPSILON {
+            return Err(NoiseSettingsError::Range);
+        }
+        Ok(())
+    }
+
+    fn to_config(self, seed: u64) -> NoiseConfig {
+        NoiseConfig::new(
+            self.octaves,
+            self.x_frequency,
+            self.y_frequency,
+            self.amplitude,
+            self.lacunarity,
+            self.gain,
+            self.range,
+            seed,
+        )
+    }
+}
+
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        Self::ROCKY
+    }
+}
 
 pub struct Noise {
+    seed: u64,
+    settings: NoiseSettings,
+    simplex: NoiseConfig,
     image: OnceCell<Image>,
     texture: OnceCell<Texture2D>,
 }
 
 impl Noise {
-    pub const fn new() -> Self {
-        Self {
+    pub fn new(seed: u64, settings: NoiseSettings) -> Result<Self, NoiseSettingsError> {
+        settings.validate()?;
+        Ok(Self {
+            seed,
+            settings,
+            simplex: settings.to_config(seed),
             image: OnceCell::new(),
             texture: OnceCell::new(),
+        })
+    }
+
+    /// Swaps the settings, the cached image and texture are regenerated on next use.
+    pub fn set_settings(&mut self, settings: NoiseSettings) -> Result<(), NoiseSettingsError> {
+        settings.validate()?;
+        self.settings = settings;
+        self.reset();
+        Ok(())
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.simplex = self.settings.to_config(self.seed);
+        self.image = OnceCell::new();
+        if let Some(texture) = self.texture.take() {
+            texture.delete();
         }
     }
 
-    pub fn get_point(&self, x: u32, y: u32) -> f32 {
-        self.image.get_or_init(Self::gen_image).get_pixel(x, y).r
+    pub const fn seed(&self) -> u64 {
+        self.seed
     }
 
-    pub fn gen_image() -> Image {
-        let simplex: NoiseConfig = NoiseConfig::new(
-            4,               // Octaves
-            0.008,           // X-Frequency
-            0.008,           // Y-Frequency
-            0.05,            // Amplitude
-            3.0,             // Lacunarity
-            0.25,            // Gain
-            (0.0, 1.0),      // range
-            random::<u64>(), // seed
-        );
+    pub const fn settings(&self) -> NoiseSettings {
+        self.settings
+    }
+
+    /// Samples the noise directly, without building the debug image.
+    pub fn sample(&self, x: f32, y: f32) -> f32 {
+        self.simplex.generate_range(x, y)
+    }
+
+    /// Samples the noise at `point`, mapped from the settings' range to `[0, 1]`.
+    pub fn sample_unit(&self, point: Vec2) -> f32 {
+        let (from, to) = self.settings.range;
+        (self.sample(point.x, point.y) - from) / (to - from)
+    }
+
+    /// Samples the noise along a circle, so the values wrap around seamlessly at `TAU`.
+    /// Mapped to `[0, 1]` like `sample_unit`, whatever the settings.
+    pub fn sample_circle(&self, origin: Vec2, radius: f32, angle: f32) -> f32 {
+        self.sample_unit(vec2(
+            angle.sin().mul_add(radius, origin.x),
+            angle.cos().mul_add(radius, origin.y),
+        ))
+    }
 
+    pub fn gen_image(&self) -> Image {
         let mut image = Image::gen_image_color(NOISE_SIZE, NOISE_SIZE, color_u8!(255, 0, 255, 255));
 
         for y in 0..NOISE_SIZE {
             for x in 0..NOISE_SIZE {
-                let color = simplex.generate_range(x.into(), y.into());
+                let color = self.simplex.generate_range(x.into(), y.into());
                 let color = Color {
                     r: color,
                     g: color,
@@ -54,7 +230,7 @@ impl Noise {
         draw_texture(
             *self
                 .texture
-                .get_or_init(|| Texture2D::from_image(self.image.get_or_init(Self::gen_image))),
+                .get_or_init(|| Texture2D::from_image(self.image.get_or_init(|| self.gen_image()))),
             x,
             y,
             color_u8!(255, 255, 255, 255),
@@ -0,0 +1,252 @@
+use macroquad::prelude::*;
+
+use crate::common::*;
+
+/// A color at a position along a gradient.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorStop {
+    pub at: f32,
+    pub color: Color,
+}
+
+const fn stop(at: f32, color: Color) -> ColorStop {
+    ColorStop { at, color }
+}
+
+/// How much darker the back wall of a cave is than the ground around it.
+const HOLLOW_SHADE: f32 = 0.6;
+
+/// A layer of the planet's interior, reaching from `depth` down to the next layer.
+/// Depths are fractions of the base radius below it: 0 is the base radius, 1 the center.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stratum {
+    pub name: &'static str,
+    pub depth: f32,
+    pub color: Color,
+}
+
+const fn stratum(name: &'static str, depth: f32, color: Color) -> Stratum {
+    Stratum { name, depth, color }
+}
+
+/// How a planet is colored, positions along the gradients are altitudes normalized
+/// to the planet: 0 is the base radius and 1 the highest the noise can raise the ground.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub name: &'static str,
+    /// Ground colors by altitude.
+    pub ground: Vec<ColorStop>,
+    /// Ground colors by altitude where the moisture noise is high, empty for no moisture.
+    pub wet_ground: Vec<ColorStop>,
+    /// Hard color bands at the stops instead of smooth gradients between them.
+    pub banded: bool,
+    /// Latitude, 0 at the equator and 1 at the poles, above which the ground is `polar`.
+    pub polar_cap: Option<f32>,
+    pub polar: Color,
+    /// Layers under the base radius, ordered from the surface down.
+    pub interior: Vec<Stratum>,
+    /// Water at the surface of the ocean.
+    pub water: Color,
+    /// Water as deep under the sea level as the highest ground is above the base radius.
+    pub deep_water: Color,
+}
+
+impl Palette {
+    #[must_use]
+    pub fn earth() -> Self {
+        Self {
+            name: "earth",
+            ground: vec![
+                stop(0.0, color_u8!(221, 181, 110, 255)),
+                stop(0.133, color_u8!(178, 166, 72, 255)),
+                stop(0.267, color_u8!(85, 150, 83, 255)),
+                stop(0.4, color_u8!(141, 153, 40, 255)),
+                stop(0.533, color_u8!(125, 165, 123, 255)),
+                stop(0.667, color_u8!(193, 212, 169, 255)),
+                stop(0.8, color_u8!(255, 255, 212, 255)),
+                stop(0.802, color_u8!(255, 255, 255, 255)),
+            ],
+            wet_ground: vec![
+                stop(0.0, color_u8!(150, 140, 90, 255)),
+                stop(0.133, color_u8!(60, 120, 60, 255)),
+                stop(0.4, color_u8!(40, 100, 50, 255)),
+                stop(0.667, color_u8!(110, 140, 110, 255)),
+                stop(0.8, color_u8!(255, 255, 255, 255)),
+            ],
+            banded: true,
+            polar_cap: Some(0.93),
+            polar: color_u8!(240, 245, 250, 255),
+            interior: vec![
+                stratum("crust", 0.0, color_u8!(110, 90, 55, 255)),
+                stratum("mantle", 0.25, color_u8!(150, 70, 40, 255)),
+                stratum("outer core", 0.6, color_u8!(220, 120, 40, 255)),
+                stratum("inner core", 0.85, color_u8!(250, 210, 120, 255)),
+            ],
+            water: color_u8!(13, 148, 138, 255),
+            deep_water: color_u8!(5, 50, 75, 255),
+        }
+    }
+
+    #[must_use]
+    pub fn desert() -> Self {
+        Self {
+            name: "desert",
+            ground: vec![
+                stop(0.0, color_u8!(237, 201, 140, 255)),
+                stop(0.4, color_u8!(214, 164, 98, 255)),
+                stop(0.7, color_u8!(168, 112, 70, 255)),
+                stop(1.0, color_u8!(120, 80, 60, 255)),
+            ],
+            wet_ground: vec![
+                stop(0.0, color_u8!(120, 150, 70, 255)),
+                stop(0.3, color_u8!(190, 170, 100, 255)),
+            ],
+            banded: false,
+            polar_cap: None,
+            polar: color_u8!(255, 255, 255, 255),
+            interior: vec![
+                stratum("crust", 0.0, color_u8!(170, 120, 75, 255)),
+                stratum("mantle", 0.3, color_u8!(140, 70, 45, 255)),
+                stratum("core", 0.7, color_u8!(230, 150, 60, 255)),
+            ],
+            water: color_u8!(40, 150, 160, 255),
+            deep_water: color_u8!(15, 70, 100, 255),
+        }
+    }
+
+    #[must_use]
+    pub fn ice() -> Self {
+        Self {
+            name: "ice",
+            ground: vec![
+                stop(0.0, color_u8!(170, 200, 220, 255)),
+                stop(0.5, color_u8!(215, 232, 242, 255)),
+                stop(1.0, color_u8!(255, 255, 255, 255)),
+            ],
+            wet_ground: Vec::new(),
+            banded: false,
+            polar_cap: Some(0.7),
+            polar: color_u8!(250, 252, 255, 255),
+            interior: vec![
+                stratum("ice sheet", 0.0, color_u8!(120, 150, 180, 255)),
+                stratum("mantle", 0.2, color_u8!(70, 80, 100, 255)),
+                stratum("core", 0.65, color_u8!(200, 120, 70, 255)),
+            ],
+            water: color_u8!(60, 90, 140, 255),
+            deep_water: color_u8!(20, 30, 65, 255),
+        }
+    }
+
+    #[must_use]
+    pub fn lava() -> Self {
+        Self {
+            name: "lava",
+            ground: vec![
+                stop(0.0, color_u8!(70, 40, 35, 255)),
+                stop(0.5, color_u8!(45, 35, 35, 255)),
+                stop(1.0, color_u8!(25, 20, 20, 255)),
+            ],
+            wet_ground: vec![
+                stop(0.0, color_u8!(255, 110, 20, 255)),
+                stop(0.3, color_u8!(200, 50, 20, 255)),
+                stop(0.6, color_u8!(60, 30, 30, 255)),
+            ],
+            banded: false,
+            polar_cap: None,
+            polar: color_u8!(255, 255, 255, 255),
+            interior: vec![
+                stratum("crust", 0.0, color_u8!(50, 30, 28, 255)),
+                stratum("mantle", 0.1, color_u8!(200, 60, 20, 255)),
+                stratum("core", 0.6, color_u8!(255, 200, 80, 255)),
+            ],
+            water: color_u8!(255, 120, 0, 255),
+            deep_water: color_u8!(170, 30, 0, 255),
+        }
+    }
+
+    #[must_use]
+    pub fn presets() -> [Self; 4] {
+        [Self::earth(), Self::desert(), Self::ice(), Self::lava()]
+    }
+
+    #[must_use]
+    pub fn preset(name: &str) -> Option<Self> {
+        Self::presets()
+            .into_iter()
+            .find(|palette| palette.name == name)
+    }
+
+    /// Color of the ground at a normalized `altitude`, `latitude` and `moisture`, all in `[0, 1]`.
+    #[must_use]
+    pub fn ground_color(&self, altitude: f32, latitude: f32, moisture: f32) -> Color {
+        if self.polar_cap.is_some_and(|cap| latitude > cap) {
+            return self.polar;
+        }
+        let dry = self.sample(&self.ground, altitude);
+        if self.wet_ground.is_empty() {
+            return dry;
+        }
+        let wet = self.sample(&self.wet_ground, altitude);
+        mix(dry, wet, moisture.clamp(0.0, 1.0))
+    }
+
+    /// The layer of the interior at `depth` under the base radius, a fraction of the radius.
+    #[must_use]
+    pub fn stratum(&self, depth: f32) -> Option<&Stratum> {
+        self.interior
+            .iter()
+            .take_while(|stratum| stratum.depth <= depth)
+            .last()
+            .or_else(|| self.interior.first())
+    }
+
+    #[must_use]
+    pub fn interior_color(&self, depth: f32) -> Color {
+        self.stratum(depth).map_or(BLANK, |stratum| stratum.color)
+    }
+
+    /// Color of the back wall of a cave, the ground at `depth` but in shadow.
+    #[must_use]
+    pub fn hollow_color(&self, depth: f32) -> Color {
+        mix(self.interior_color(depth), BLACK, HOLLOW_SHADE)
+    }
+
+    /// Color of the ocean `depth` under the sea level, normalized like the altitudes.
+    #[must_use]
+    pub fn water_color(&self, depth: f32) -> Color {
+        mix(self.water, self.deep_water, depth.clamp(0.0, 1.0))
+    }
+
+    fn sample(&self, stops: &[ColorStop], at: f32) -> Color {
+        let next = stops.iter().position(|stop| stop.at > at);
+        let Some(next) = next else {
+            return stops.last().map_or(BLANK, |stop| stop.color);
+        };
+        let (Some(to), Some(from)) = (
+            stops.get(next),
+            next.checked_sub(1).and_then(|index| stops.get(index)),
+        ) else {
+            return stops.first().map_or(BLANK, |stop| stop.color);
+        };
+        if self.banded {
+            from.color
+        } else {
+            mix(from.color, to.color, norm(at, from.at, to.at))
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::earth()
+    }
+}
+
+fn mix(from: Color, to: Color, p: f32) -> Color {
+    Color::new(
+        lerp(from.r, to.r, p),
+        lerp(from.g, to.g, p),
+        lerp(from.b, to.b, p),
+        lerp(from.a, to.a, p),
+    )
+}
@@ -1,11 +1,95 @@
+use std::ops::Range;
+
 use macroquad::prelude::*;
 
+use crate::collision::{self, Contact};
 use crate::common::*;
+use crate::density::{DensityField, DensityRow};
 use crate::noise::Noise;
-use crate::world::NOISE_SIZE;
+use crate::palette::{Palette, Stratum};
+
+/// Fewest surface points a planet can be made of.
+pub const MIN_RESOLUTION: usize = 3;
+/// Radius of the circle the surface heights are sampled along, in noise space.
+const NOISE_CIRCLE_RADIUS: f32 = 180.0;
+/// How far the sampling circle may be moved in noise space by the seed.
+const NOISE_MAX_OFFSET: f32 = 10_000.0;
+/// Surface gravity per unit of radius, all planets are assumed to be equally dense.
+const GRAVITY_PER_RADIUS: f32 = 0.4;
+/// Highest the noise can raise the ground, as a fraction of the radius.
+const MAX_HEIGHT: f32 = 0.5;
+/// Moisture noise below this is dry ground, it is fully wet a bit above.
+const WET_THRESHOLD: f32 = 0.6;
+/// Sea level new planets start with, as a normalized altitude.
+pub const DEFAULT_SEA_LEVEL: f32 = 0.27;
+/// Fewest rows worth a thread of their own. Small regions, like the patches terrain edits
+/// re-render, are quicker to rasterize on the calling thread than to spawn threads for.
+const ROWS_PER_THREAD: usize = 64;
+/// Spacing of the density samples caves are carved into.
+const CAVE_CELL_SIZE: f32 = 8.0;
+/// How far overhangs may reach out of or into the radial surface.
+const OVERHANG_DEPTH: f32 = 60.0;
+/// How close to the middle of the noise range tunnels run, wider values make wider tunnels.
+const TUNNEL_WIDTH: f32 = 0.06;
+/// Below this fraction of the radius the ground is too dense for tunnels.
+const TUNNEL_FLOOR: f32 = 0.8;
+/// Stretches the noise out in the world, so caves are bigger than the grain of the surface noise.
+const CAVE_NOISE_SCALE: f32 = 0.2;
+
+/// What the rasterizer knows about a planet before looking at any pixel.
+struct RasterBounds {
+    /// Everything closer to the center is under the radial surface.
+    inner: f32,
+    /// Everything farther from the center is empty space.
+    outer: f32,
+    /// For each surface segment, a distance the surface around it reaches everywhere,
+    /// and a distance it does not reach anywhere.
+    floors: Vec<f32>,
+    ceilings: Vec<f32>,
+    /// The palette's interior layers, by the depth each starts at, with colors ready to copy.
+    strata: Vec<(f32, [u8; 4])>,
+}
+
+impl RasterBounds {
+    /// Whether the pixel `offset` from the center and `distance` away from it is under the
+    /// radial surface. `None` when it is too close to the surface to tell.
+    fn is_under_surface(&self, offset: Vec2, distance: f32) -> Option<bool> {
+        if distance < self.inner {
+            return Some(true);
+        }
+        let angle = approximate_atan2(offset.x, offset.y).rem_euclid(std::f32::consts::TAU);
+        let (segment, _, _) = ring_position(angle, self.ceilings.len());
+        match (self.floors.get(segment), self.ceilings.get(segment)) {
+            (Some(&floor), _) if distance < floor => Some(true),
+            (_, Some(&ceiling)) if distance > ceiling => Some(false),
+            _ => None,
+        }
+    }
+
+    /// How many of the interior layers start no deeper than `depth`, never fewer further down.
+    fn layer(&self, depth: f32) -> usize {
+        self.strata
+            .iter()
+            .take_while(|&&(top, _)| top <= depth)
+            .count()
+    }
+
+    /// The same color as `Palette::interior_color` at the depth `layer` is at.
+    fn layer_color(&self, layer: usize) -> [u8; 4] {
+        self.strata
+            .get(layer.saturating_sub(1))
+            .map_or_else(|| BLANK.into(), |&(_, color)| color)
+    }
+}
 
-const MULTIPLE_360: u8 = 2;
+/// Whether a terrain edit takes ground away or piles it up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerrainEdit {
+    Dig,
+    Build,
+}
 
+#[derive(Clone)]
 pub struct Planet {
     pub center: Vec2,
     radius: f32,
@@ -13,12 +97,22 @@ pub struct Planet {
     pub extents: Rect,
 
     pub surface: Vec<Vec2>,
+    /// Moisture at each surface point's angle, in `[0, 1]`.
+    moisture: Vec<f32>,
+    pub palette: Palette,
+    /// Altitude the oceans are filled to, normalized like the palette: 0 is the base
+    /// radius and 1 the highest the noise can raise the ground. `None` for a dry planet.
+    pub sea_level: Option<f32>,
+    /// When present, the solid terrain is taken from here instead of `surface`.
+    caves: Option<DensityField>,
 }
 
 impl Planet {
-    pub fn new(center: Vec2, radius: f32, noise: &Noise) -> Self {
-        let surface =
-            Self::create_surface(360 * usize::from(MULTIPLE_360) + 1, center, radius, noise);
+    /// Creates a planet with `resolution` surface points spread evenly around the `center`.
+    pub fn new(center: Vec2, radius: f32, resolution: usize, noise: &Noise) -> Self {
+        let resolution = resolution.max(MIN_RESOLUTION);
+        let surface = Self::create_surface(resolution, center, radius, noise);
+        let moisture = Self::create_moisture(resolution, noise);
         let (extents, max_radius) = Self::calculate_extents(&surface, center);
         Self {
             center,
@@ -26,9 +120,84 @@ impl Planet {
             max_radius,
             extents,
             surface,
+            moisture,
+            palette: Palette::default(),
+            sea_level: Some(DEFAULT_SEA_LEVEL),
+            caves: None,
         }
     }
 
+    /// Rebuilds a planet around an already shaped `surface`, like one with terrain edits
+    /// loaded from a save. The moisture doesn't change with edits and is taken from `noise`.
+    pub fn from_surface(center: Vec2, radius: f32, surface: Vec<Vec2>, noise: &Noise) -> Self {
+        let moisture = Self::create_moisture(surface.len(), noise);
+        let (extents, max_radius) = Self::calculate_extents(&surface, center);
+        Self {
+            center,
+            radius,
+            max_radius,
+            extents,
+            surface,
+            moisture,
+            palette: Palette::default(),
+            sea_level: Some(DEFAULT_SEA_LEVEL),
+            caves: None,
+        }
+    }
+
+    /// Switches the planet to a density field representation, with overhangs and tunnels.
+    /// The radial `surface` is kept as the base shape the field is built around.
+    pub fn carve_caves(&mut self, noise: &Noise) {
+        let offset = |stream| {
+            vec2(
+                seeded_range(noise.seed(), stream, 0.0, NOISE_MAX_OFFSET).floor(),
+                seeded_range(noise.seed(), stream + 1, 0.0, NOISE_MAX_OFFSET).floor(),
+            )
+        };
+        let (overhang_offset, tunnel_offset) = (offset(2), offset(4));
+        let margin = OVERHANG_DEPTH + CAVE_CELL_SIZE;
+        let bounds = Rect::new(
+            self.extents.x - margin,
+            self.extents.y - margin,
+            margin.mul_add(2.0, self.extents.w),
+            margin.mul_add(2.0, self.extents.h),
+        );
+        let field = DensityField::new(bounds, CAVE_CELL_SIZE, |point| {
+            let depth = -self.altitude(point);
+            let overhang = (noise.sample_unit(point * CAVE_NOISE_SCALE + overhang_offset) - 0.5)
+                * 2.0
+                * OVERHANG_DEPTH;
+            let ridge = (noise.sample_unit(point * CAVE_NOISE_SCALE + tunnel_offset) - 0.5).abs();
+            let tunnel = if point.distance(self.center) > self.radius * TUNNEL_FLOOR {
+                (1.0 - ridge / TUNNEL_WIDTH).max(0.0) * self.radius
+            } else {
+                0.0
+            };
+            (depth + overhang - tunnel) / CAVE_CELL_SIZE
+        });
+        self.set_caves(field);
+    }
+
+    /// Takes the solid terrain from `caves`, like a saved planet's carved and edited caves.
+    pub fn set_caves(&mut self, caves: DensityField) {
+        (self.extents, self.max_radius) = caves.extents(self.center);
+        self.caves = Some(caves);
+    }
+
+    /// Goes back to the radial surface, dropping the caves.
+    pub fn fill_caves(&mut self) {
+        self.caves = None;
+        (self.extents, self.max_radius) = Self::calculate_extents(&self.surface, self.center);
+    }
+
+    pub const fn has_caves(&self) -> bool {
+        self.caves.is_some()
+    }
+
+    pub const fn caves(&self) -> Option<&DensityField> {
+        self.caves.as_ref()
+    }
+
     fn create_surface(
         surface_points: usize,
         center: Vec2,
@@ -36,23 +205,34 @@ impl Planet {
         noise: &Noise,
     ) -> Vec<Vec2> {
         let mut surface: Vec<Vec2> = Vec::with_capacity(surface_points);
-        let padding = 200.0;
-        let xoffset: i16 =
-            macroquad::rand::gen_range(padding as i16, NOISE_SIZE as i16 - padding as i16);
-        let yoffset: i16 =
-            macroquad::rand::gen_range(padding as i16, NOISE_SIZE as i16 - padding as i16);
+        let offset = vec2(
+            seeded_range(noise.seed(), 0, 0.0, NOISE_MAX_OFFSET).floor(),
+            seeded_range(noise.seed(), 1, 0.0, NOISE_MAX_OFFSET).floor(),
+        );
         for point in 0..surface_points {
-            let a = point as f32 * std::f32::consts::TAU / surface_points as f32;
-            let height = noise.get_point(
-                a.sin().mul_add(padding - 20.0, f32::from(xoffset)) as u32,
-                a.cos().mul_add(padding - 20.0, f32::from(yoffset)) as u32,
-            ) * (radius / 2.0);
+            let a = ring_angle(point, surface_points);
+            let height =
+                noise.sample_circle(offset, NOISE_CIRCLE_RADIUS, a) * (radius * MAX_HEIGHT);
             surface.push(center + vec2((radius + height) * a.sin(), (radius + height) * a.cos()));
         }
 
         surface
     }
 
+    fn create_moisture(surface_points: usize, noise: &Noise) -> Vec<f32> {
+        let offset = vec2(
+            seeded_range(noise.seed(), 6, 0.0, NOISE_MAX_OFFSET).floor(),
+            seeded_range(noise.seed(), 7, 0.0, NOISE_MAX_OFFSET).floor(),
+        );
+        (0..surface_points)
+            .map(|point| {
+                let a = ring_angle(point, surface_points);
+                let wetness = noise.sample_circle(offset, NOISE_CIRCLE_RADIUS, a);
+                norm(wetness, WET_THRESHOLD, WET_THRESHOLD + 0.1).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+
     fn calculate_extents(surface: &[Vec2], center: Vec2) -> (Rect, f32) {
         let mut min_x = std::f32::INFINITY;
         let mut min_y = std::f32::INFINITY;
@@ -84,103 +264,267 @@ impl Planet {
         (Rect::new(min_x, min_y, width, height), max_radius)
     }
 
-    pub fn is_inside_expensive(planet: &Self, point: Vec2) -> bool {
-        let angle = point.angle_between(Vec2::new(0.0, 1.0));
-        let mut left_min = -10.0;
-        let mut right_min = 10.0;
-        let mut left = 0.0;
-        let mut right = 0.0;
-        let mut left_seg = Vec2::new(0.0, 0.0);
-        let mut right_seg = Vec2::new(0.0, 0.0);
-        for segment in &planet.surface {
-            let segment_angle = segment.angle_between(Vec2::new(0.0, 1.0));
-            let diff = angle - segment_angle;
-            if diff < right_min && diff > 0.0 {
-                right_min = diff;
-                right = segment_angle;
-                right_seg = *segment;
+    /// Whether `point` lies under the surface, for a planet anywhere in the world.
+    /// Points exactly on the surface are outside.
+    pub fn contains_point(planet: &Self, point: Vec2) -> bool {
+        if let Some(caves) = planet.caves.as_ref() {
+            return caves.is_solid(point);
+        }
+        let distance = point.distance(planet.center);
+        distance <= planet.max_radius && Self::is_inside(planet, distance, planet.angle_of(point))
+    }
+
+    /// The boundary of the ground as polylines, closed ones end on the point they start from.
+    /// The radial surface is a single closed polyline, caves can make many.
+    #[must_use]
+    pub fn outline(&self) -> Vec<Vec<Vec2>> {
+        self.caves.as_ref().map_or_else(
+            || {
+                let mut outline = self.surface.clone();
+                outline.extend(self.surface.first().copied());
+                vec![outline]
+            },
+            DensityField::contour_polylines,
+        )
+    }
+
+    pub const fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Distance from the center to the surface of the oceans.
+    #[must_use]
+    pub fn sea_radius(&self) -> Option<f32> {
+        self.sea_level
+            .map(|level| (level * MAX_HEIGHT).mul_add(self.radius, self.radius))
+    }
+
+    /// Whether `point` is in the ocean, oceans fill everything under the sea level that is not ground.
+    #[must_use]
+    pub fn is_underwater(&self, point: Vec2) -> bool {
+        self.sea_radius()
+            .is_some_and(|sea_radius| point.distance(self.center) < sea_radius)
+            && !Self::contains_point(self, point)
+    }
+
+    /// The area of the world the planet covers, its terrain and its oceans.
+    #[must_use]
+    pub fn image_extents(&self) -> Rect {
+        let Some(sea_radius) = self.sea_radius() else {
+            return self.extents;
+        };
+        let ocean = Rect::new(
+            self.center.x - sea_radius,
+            self.center.y - sea_radius,
+            sea_radius * 2.0,
+            sea_radius * 2.0,
+        );
+        self.extents.combine_with(ocean)
+    }
+
+    /// Standard gravitational parameter, `G * M`.
+    #[must_use]
+    pub fn gravitational_parameter(&self) -> f32 {
+        GRAVITY_PER_RADIUS * self.radius.powi(3)
+    }
+
+    /// Gravitational acceleration at `point`, it stops growing below the base radius.
+    #[must_use]
+    pub fn gravity_at(&self, point: Vec2) -> Vec2 {
+        let offset = self.center - point;
+        let distance = offset.length().max(self.radius);
+        offset.normalize_or_zero() * self.gravitational_parameter() / distance.powi(2)
+    }
+
+    /// Signed distance from the surface to `point`, measured along the line from the center.
+    /// With caves this is the distance to the radial surface they were carved around.
+    #[must_use]
+    pub fn altitude(&self, point: Vec2) -> f32 {
+        let surface_distance = self
+            .surface_point(self.angle_of(point))
+            .distance(self.center);
+        point.distance(self.center) - surface_distance
+    }
+
+    /// Angle of `point` around the planet's center, in the same `[0, TAU)` space as `surface`.
+    #[must_use]
+    pub fn angle_of(&self, point: Vec2) -> f32 {
+        let offset = point - self.center;
+        offset.x.atan2(offset.y).rem_euclid(std::f32::consts::TAU)
+    }
+
+    /// The surface point at `angle`, interpolated between the two closest samples.
+    #[must_use]
+    pub fn surface_point(&self, angle: f32) -> Vec2 {
+        let (p1, p2, decimal) = self.surface_segment(angle);
+        Vec2::new(lerp(p1.x, p2.x, decimal), lerp(p1.y, p2.y, decimal))
+    }
+
+    /// Outward facing normal of the surface at `angle`.
+    #[must_use]
+    pub fn surface_normal(&self, angle: f32) -> Vec2 {
+        let (p1, p2, _) = self.surface_segment(angle);
+        let tangent = p2 - p1;
+        Vec2::new(-tangent.y, tangent.x).normalize_or_zero()
+    }
+
+    /// Sweeps a point moving from `from` to `to` against the surface, returning the first contact.
+    #[must_use]
+    pub fn sweep(&self, from: Vec2, to: Vec2) -> Option<Contact> {
+        if let Some(caves) = self.caves.as_ref() {
+            return caves.sweep(from, to);
+        }
+        let len = self.surface.len();
+        let start = self.angle_of(from);
+        let delta = angle_difference(start, self.angle_of(to));
+        // Away from the center a straight move sweeps a narrow wedge, only test the segments in it:
+        // the ones the ends are on, everything between and a neighbour on either side.
+        let (first, count) = if delta.abs() < std::f32::consts::FRAC_PI_2 {
+            let (low, _, _) = ring_position(start.min(start + delta), len);
+            let (high, _, _) = ring_position(start.max(start + delta), len);
+            (low + len - 1, ((high + len - low) % len + 3).min(len))
+        } else {
+            (0, len)
+        };
+        let point = |index: usize| {
+            self.surface
+                .get(index % len)
+                .copied()
+                .unwrap_or(self.center)
+        };
+        let segments = (first..first + count).map(|index| (point(index), point(index + 1)));
+        collision::sweep(segments, from, to)
+    }
+
+    /// The two surface points around `angle` and how far between them `angle` is.
+    fn surface_segment(&self, angle: f32) -> (Vec2, Vec2, f32) {
+        let (index, next, fraction) = ring_position(angle, self.surface.len());
+        let point = |index| self.surface.get(index).copied().unwrap_or(self.center);
+        (point(index), point(next), fraction)
+    }
+
+    pub fn is_inside(planet: &Self, distance: f32, angle: f32) -> bool {
+        distance < planet.surface_point(angle).distance(planet.center)
+    }
+
+    /// Digs a crater into, or piles ground onto, the surface within `radius` of `point`.
+    /// Returns the area of the world that changed.
+    pub fn edit_terrain(&mut self, point: Vec2, radius: f32, edit: TerrainEdit) -> Option<Rect> {
+        // Keeps the surface from folding over the center.
+        const MIN_DISTANCE: f32 = 1.0;
+        if let Some(caves) = self.caves.as_mut() {
+            let dirty = caves.edit(point, radius, edit)?;
+            (self.extents, self.max_radius) = caves.extents(self.center);
+            return Some(dirty);
+        }
+        let len = self.surface.len();
+        let offset = point - self.center;
+        let mut changed = Vec::new();
+        for (index, surface_point) in self.surface.iter_mut().enumerate() {
+            let direction = (*surface_point - self.center).normalize_or_zero();
+            // Where the ray from the center along `direction` enters and leaves the circle.
+            let along = offset.dot(direction);
+            let discriminant =
+                along.mul_add(along, radius.mul_add(radius, -offset.length_squared()));
+            if discriminant < 0.0 {
+                continue;
             }
-            if diff > left_min && diff < 0.0 {
-                left_min = diff;
-                left = segment_angle;
-                left_seg = *segment;
+            let (near, far) = (along - discriminant.sqrt(), along + discriminant.sqrt());
+            let distance = surface_point.distance(self.center);
+            let new_distance = match edit {
+                TerrainEdit::Dig if distance > near && distance <= far => near.max(MIN_DISTANCE),
+                TerrainEdit::Build if distance >= near && distance < far => far,
+                TerrainEdit::Dig | TerrainEdit::Build => continue,
+            };
+            *surface_point = self.center + direction * new_distance;
+            changed.push(index);
+        }
+        if changed.is_empty() {
+            return None;
+        }
+
+        let (extents, max_radius) = Self::calculate_extents(&self.surface, self.center);
+        self.extents = extents;
+        self.max_radius = max_radius;
+        // Old and new points are all inside the circle, but the segments to their neighbours are not.
+        let mut dirty = Rect::new(
+            point.x - radius,
+            point.y - radius,
+            radius * 2.0,
+            radius * 2.0,
+        );
+        for index in changed {
+            for neighbour in [index + len - 1, index + 1] {
+                if let Some(neighbour) = self.surface.get(neighbour % len) {
+                    dirty = dirty.combine_with(Rect::new(neighbour.x, neighbour.y, 0.0, 0.0));
+                }
             }
         }
-        let procent = map(angle, left, right, 0.0, 1.0);
-        let lerp_x = lerp(left_seg.x, right_seg.x, procent);
-        let lerp_y = lerp(left_seg.y, right_seg.y, procent);
-        let surface_point = Vec2::new(lerp_x, lerp_y);
-        point.distance(planet.center) < surface_point.distance(planet.center)
+        Some(dirty)
     }
 
-    pub fn is_inside(planet: &Self, distance: f32, angle: f32) -> bool {
-        debug_assert!(angle >= 0.0);
-        let index = angle.ceil() as usize;
-        let other_index = if angle < 1.0 {
-            planet.surface.len() - 1
-        } else {
-            index - 1
+    /// Rasterizes the whole planet, `None` when it is too big for an image.
+    pub fn as_image(planet: &Self) -> Option<Image> {
+        let Rect { x, y, w, h } = planet.image_extents();
+        let (width, height) = (pixels(w)?, pixels(h)?);
+        Some(Self::as_image_region(planet, vec2(x, y), width, height))
+    }
+
+    /// Rasterizes `width` by `height` pixels of the planet, starting at `origin` in the world.
+    /// Rows are split between threads, and only the pixels near the surface are looked at
+    /// closely, the rest are space or interior as far as their distance from the center tells.
+    pub fn as_image_region(planet: &Self, origin: Vec2, width: u16, height: u16) -> Image {
+        let row_size = usize::from(width) * 4;
+        let mut bytes: Vec<u8> = vec![0; row_size * usize::from(height)];
+        let bounds = &planet.raster_bounds();
+        let threads = std::thread::available_parallelism()
+            .map_or(1, std::num::NonZeroUsize::get)
+            .min(usize::from(height).div_euclid(ROWS_PER_THREAD))
+            .max(1);
+        // Rows are dealt out in turn, so every thread gets some of the busy rows across the middle.
+        let mut rows_by_thread: Vec<Vec<(u16, &mut [u8])>> =
+            (0..threads).map(|_| Vec::new()).collect();
+        for (row, row_bytes) in (0..height).zip(bytes.chunks_mut(row_size.max(1))) {
+            if let Some(rows) = rows_by_thread.get_mut(usize::from(row) % threads) {
+                rows.push((row, row_bytes));
+            }
+        }
+        let rasterize = move |rows: Vec<(u16, &mut [u8])>| {
+            for (row, row_bytes) in rows {
+                let y = origin.y + f32::from(row);
+                planet.rasterize_row(origin.x, y, bounds, row_bytes);
+            }
         };
-        let p1 = planet.surface[other_index];
-        let p2 = planet.surface[index];
-        let decimal = angle.fract();
-        let x = lerp(p1.x, p2.x, decimal);
-        let y = lerp(p1.y, p2.y, decimal);
-        let surface_point = Vec2::new(x, y);
-        distance < surface_point.distance(planet.center)
+        if threads == 1 {
+            rows_by_thread.into_iter().for_each(rasterize);
+        } else {
+            std::thread::scope(|scope| {
+                for rows in rows_by_thread {
+                    scope.spawn(move || rasterize(rows));
+                }
+            });
+        }
+
+        Image {
+            bytes,
+            width,
+            height,
+        }
     }
 
-    pub fn as_image(planet: &Self) -> Image {
-        let Planet {
-            center,
-            radius,
-            max_radius,
-            extents,
-            surface: _,
-        } = planet;
-        debug_assert!(extents.w < f32::from(std::u16::MAX));
-        debug_assert!(extents.h < f32::from(std::u16::MAX));
-        let width = extents.w.ceil() as u16;
-        let height = extents.h.ceil() as u16;
+    /// Rasterizes the same pixels as `as_image_region` one at a time, looking at every one
+    /// of them closely. Slow, but simple enough to check the fast rasterizer against.
+    pub fn as_image_region_per_pixel(
+        planet: &Self,
+        origin: Vec2,
+        width: u16,
+        height: u16,
+    ) -> Image {
         let mut bytes: Vec<u8> = Vec::with_capacity(usize::from(width) * usize::from(height) * 4);
-
         for y in 0..height {
             for x in 0..width {
-                let point = Vec2::new(extents.x + f32::from(x), extents.y + f32::from(y));
-                let distance = point.distance(*center);
-
-                if distance < *radius {
-                    // outer space
-                    let color: [u8; 4] = color_u8!(110, 90, 55, 255).into();
-                    bytes.extend(color);
-                } else if distance > *max_radius {
-                    // under the planet's crust
-                    let color: [u8; 4] = color_u8!(0, 0, 0, 0).into();
-                    bytes.extend(color);
-                } else {
-                    let angle = (std::f32::consts::PI + point.angle_between(Vec2::new(0.0, -1.0)))
-                        .to_degrees()
-                        * f32::from(MULTIPLE_360);
-                    let up = (distance - *radius).floor() as i32;
-                    let color: [u8; 4] = if Self::is_inside(planet, distance, angle) {
-                        match up {
-                            0..=99 => color_u8!(221, 181, 110, 255).into(),
-                            100..=199 => color_u8!(178, 166, 72, 255).into(),
-                            200..=299 => color_u8!(85, 150, 83, 255).into(),
-                            300..=399 => color_u8!(141, 153, 40, 255).into(),
-                            400..=499 => color_u8!(125, 165, 123, 255).into(),
-                            500..=599 => color_u8!(193, 212, 169, 255).into(),
-                            600..=600 => color_u8!(255, 255, 212, 255).into(),
-                            _ => color_u8!(255, 255, 255, 255).into(),
-                        }
-                    } else if up < 200 {
-                        color_u8!(13, 148, 138, 255).into()
-                    } else {
-                        // outer space;
-                        color_u8!(0, 0, 0, 0).into()
-                    };
-                    bytes.extend(color);
-                };
+                let point = Vec2::new(origin.x + f32::from(x), origin.y + f32::from(y));
+                bytes.extend(planet.color_at(point));
             }
         }
 
@@ -191,6 +535,262 @@ impl Planet {
         }
     }
 
+    fn raster_bounds(&self) -> RasterBounds {
+        let sea_radius = self.sea_radius().unwrap_or(0.0);
+        let len = self.surface.len();
+        let distances: Vec<f32> = self
+            .surface
+            .iter()
+            .map(|point| point.distance(self.center))
+            .collect();
+        let lowest = distances.iter().copied().fold(f32::INFINITY, f32::min);
+        // Between two surface points the line connecting them sags towards the center,
+        // but never rises above its higher end. The bounds of a segment include its neighbours,
+        // so a pixel put in the wrong segment by an approximate angle is still covered.
+        let sag = (ring_angle(1, len) / 2.0).cos();
+        let neighbourhood = |segment: usize| {
+            (segment + len - 1..=segment + len + 2)
+                .filter_map(|index| distances.get(index % len).copied())
+        };
+        let floors = (0..len)
+            .map(|segment| {
+                neighbourhood(segment)
+                    .fold(f32::INFINITY, f32::min)
+                    .mul_add(sag, -1.0)
+            })
+            .collect();
+        let ceilings = (0..len)
+            .map(|segment| neighbourhood(segment).fold(0.0, f32::max) + 1.0)
+            .collect();
+        let outer = if self.caves.is_some() {
+            // The bilinear density can bulge a little past the contour it was marched into.
+            CAVE_CELL_SIZE.mul_add(2.0, self.max_radius)
+        } else {
+            self.max_radius
+        };
+        RasterBounds {
+            inner: lowest.mul_add(sag, -1.0),
+            outer: outer.max(sea_radius),
+            floors,
+            ceilings,
+            strata: self
+                .palette
+                .interior
+                .iter()
+                .map(|stratum| (stratum.depth, stratum.color.into()))
+                .collect(),
+        }
+    }
+
+    /// Rasterizes the row of pixels at `y`, starting at `left`, into `bytes` filled with space.
+    fn rasterize_row(&self, left: f32, y: f32, bounds: &RasterBounds, bytes: &mut [u8]) {
+        let outer = bounds.outer;
+        let dy = y - self.center.y;
+        let reach_squared = outer.mul_add(outer, -dy * dy);
+        if reach_squared < 0.0 {
+            return;
+        }
+        // A pixel of slack, so rounding can't leave out pixels at the edge of the circle.
+        let reach = reach_squared.sqrt() + 1.0;
+        let first = pixels((self.center.x - reach - left).floor().max(0.0)).unwrap_or(u16::MAX);
+        let last = pixels((self.center.x + reach - left).max(0.0)).unwrap_or(u16::MAX);
+        let end = (usize::from(last) + 1).min(bytes.len().div_euclid(4));
+        // The whole row of pixels is in the same row of the density grid.
+        let caves = self.caves.as_ref().map(|caves| caves.row(y));
+        let distance_at = |x: usize| Vec2::new(left + count_to_f32(x), y).distance(self.center);
+        // Where the run of pixels from `x` on that are solid and under the base radius ends,
+        // they are colored by the interior layers alone.
+        let interior_end = |x: usize, distance: f32| {
+            let Some(caves) = caves.as_ref() else {
+                // Along the row the distance to the center falls and then rises again,
+                // so the pixels close enough to be under the surface are a single span.
+                let limit = bounds.inner.min(self.radius);
+                return (distance < limit)
+                    .then(|| partition_point(x + 1..end, |x| distance_at(x) >= limit));
+            };
+            if distance >= self.radius {
+                return None;
+            }
+            let last = caves
+                .cell(left + count_to_f32(x))
+                .and_then(|column| caves.solid_through(column))?;
+            Some(partition_point(x + 1..end, |x| {
+                distance_at(x) >= self.radius
+                    || caves
+                        .cell(left + count_to_f32(x))
+                        .is_none_or(|column| column > last)
+            }))
+        };
+        let mut x = usize::from(first);
+        while x < end {
+            let point = Vec2::new(left + count_to_f32(x), y);
+            let distance = point.distance(self.center);
+            if let Some(run_end) = interior_end(x, distance) {
+                self.fill_layers(left, y, bounds, x..run_end, bytes);
+                x = run_end;
+                continue;
+            }
+            if distance <= outer {
+                let color = self.pixel_color(point, distance, bounds, caves.as_ref());
+                if let (Some(color), Some(pixel)) = (color, bytes.get_mut(x * 4..x * 4 + 4)) {
+                    pixel.copy_from_slice(&color);
+                }
+            }
+            x += 1;
+        }
+    }
+
+    /// Color of the pixel at `point`, `distance` away from the center, `None` for space.
+    fn pixel_color(
+        &self,
+        point: Vec2,
+        distance: f32,
+        bounds: &RasterBounds,
+        caves: Option<&DensityRow>,
+    ) -> Option<[u8; 4]> {
+        let is_inside = || {
+            // Pixels too close to the surface to tell are looked at closely.
+            bounds
+                .is_under_surface(point - self.center, distance)
+                .unwrap_or_else(|| {
+                    distance <= self.max_radius
+                        && Self::is_inside(self, distance, self.angle_of(point))
+                })
+        };
+        let solid = caves.map_or_else(is_inside, |caves| caves.is_solid(point.x));
+        if solid {
+            Some(if distance < self.radius {
+                bounds.layer_color(bounds.layer((self.radius - distance) / self.radius))
+            } else {
+                self.ground_color(self.angle_of(point), distance)
+            })
+        } else if let Some(water) = self.water_color(distance) {
+            Some(water)
+        } else if caves.is_some() && is_inside() {
+            Some(self.hollow_color(distance))
+        } else {
+            None
+        }
+    }
+
+    /// Colors the pixels of `span` in the row at `y` by the interior layers, they have to be
+    /// solid and under the base radius.
+    fn fill_layers(
+        &self,
+        left: f32,
+        y: f32,
+        bounds: &RasterBounds,
+        span: Range<usize>,
+        bytes: &mut [u8],
+    ) {
+        let layer = |x: usize| {
+            let distance = Vec2::new(left + count_to_f32(x), y).distance(self.center);
+            bounds.layer((self.radius - distance) / self.radius)
+        };
+        // The pixels up to the middle get closer to the center and the ones after it farther,
+        // so on either side the layers only get deeper or only shallower, and where they
+        // change can be searched for instead of looking at every pixel.
+        let middle = floor_index((self.center.x - left).max(0.0))
+            .map_or(span.end, |middle| middle + 1)
+            .clamp(span.start, span.end);
+        for side in [span.start..middle, middle..span.end] {
+            let mut from = side.start;
+            while from < side.end {
+                let from_layer = layer(from);
+                let to = partition_point(from + 1..side.end, |x| layer(x) != from_layer);
+                let color = bounds.layer_color(from_layer);
+                let pixels = bytes.get_mut(from * 4..to * 4).unwrap_or_default();
+                for pixel in pixels.chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&color);
+                }
+                from = to;
+            }
+        }
+    }
+
+    /// Color of the planet at `point`: ground, ocean, the back wall of a cave or transparent space.
+    fn color_at(&self, point: Vec2) -> [u8; 4] {
+        let distance = point.distance(self.center);
+        let angle = self.angle_of(point);
+        let solid = self.caves.as_ref().map_or_else(
+            || distance <= self.max_radius && Self::is_inside(self, distance, angle),
+            |caves| caves.is_solid(point),
+        );
+        if solid {
+            self.solid_color(angle, distance)
+        } else if let Some(water) = self.water_color(distance) {
+            water
+        } else if self.caves.is_some() && Self::is_inside(self, distance, angle) {
+            self.hollow_color(distance)
+        } else {
+            color_u8!(0, 0, 0, 0).into()
+        }
+    }
+
+    /// Color of the solid ground at `angle`, `distance` away from the center.
+    fn solid_color(&self, angle: f32, distance: f32) -> [u8; 4] {
+        if distance < self.radius {
+            self.interior_color(distance)
+        } else {
+            self.ground_color(angle, distance)
+        }
+    }
+
+    /// Color of the ground at the `index`th surface point, or of the interior if it was dug
+    /// below the base radius.
+    #[must_use]
+    pub fn surface_color(&self, index: usize) -> Color {
+        self.surface.get(index).map_or(BLANK, |&point| {
+            self.solid_color(self.angle_of(point), point.distance(self.center))
+                .into()
+        })
+    }
+
+    /// Palette color of the back wall of a cave `distance` away from the center.
+    fn hollow_color(&self, distance: f32) -> [u8; 4] {
+        // Inside a cave, the strata it was dug through are behind it.
+        let depth = (self.radius - distance) / self.radius;
+        self.palette.hollow_color(depth.max(0.0)).into()
+    }
+
+    /// Palette color of the interior `distance` away from the center.
+    fn interior_color(&self, distance: f32) -> [u8; 4] {
+        let depth = (self.radius - distance) / self.radius;
+        self.palette.interior_color(depth).into()
+    }
+
+    /// The layer of the interior at `point`, `None` above the base radius.
+    #[must_use]
+    pub fn stratum_at(&self, point: Vec2) -> Option<&Stratum> {
+        let depth = (self.radius - point.distance(self.center)) / self.radius;
+        if depth < 0.0 {
+            return None;
+        }
+        self.palette.stratum(depth)
+    }
+
+    /// Palette color of the ground at `angle`, `distance` away from the center.
+    fn ground_color(&self, angle: f32, distance: f32) -> [u8; 4] {
+        let altitude = (distance - self.radius) / (self.radius * MAX_HEIGHT);
+        let latitude = angle.cos().abs();
+        let (index, next, fraction) = ring_position(angle, self.moisture.len());
+        let sample = |index| self.moisture.get(index).copied().unwrap_or(0.0);
+        let moisture = lerp(sample(index), sample(next), fraction);
+        self.palette
+            .ground_color(altitude, latitude, moisture)
+            .into()
+    }
+
+    /// Palette color of the ocean `distance` away from the center, `None` above the sea level.
+    fn water_color(&self, distance: f32) -> Option<[u8; 4]> {
+        let sea_radius = self.sea_radius()?;
+        if distance >= sea_radius {
+            return None;
+        }
+        let depth = (sea_radius - distance) / (self.radius * MAX_HEIGHT);
+        Some(self.palette.water_color(depth).into())
+    }
+
     pub fn draw(&self) {
         let scale = 5.0;
         draw_triangle(
@@ -202,7 +802,18 @@ impl Planet {
 
         let dotted_style = false;
         let should_draw_radius = true;
-        if dotted_style {
+        if let Some(caves) = self.caves.as_ref() {
+            for &(start, end) in &caves.contour {
+                draw_line(
+                    start.x,
+                    start.y,
+                    end.x,
+                    end.y,
+                    4.0,
+                    color_u8!(122, 122, 122, 255),
+                );
+            }
+        } else if dotted_style {
             for point in &self.surface {
                 draw_circle(point.x, point.y, 5.0, color_u8!(255, 255, 255, 255));
             }
@@ -220,6 +831,15 @@ impl Planet {
                 last_point = point;
             }
         }
+        if let Some(sea_radius) = self.sea_radius() {
+            draw_circle_lines(
+                self.center.x,
+                self.center.y,
+                sea_radius,
+                4.0,
+                self.palette.water,
+            );
+        }
         if should_draw_radius {
             let radius_brightness = 30;
             draw_circle(
@@ -239,3 +859,218 @@ impl Planet {
         draw_rectangle_lines(x, y, w, h, 10.0, color_u8!(255, 255, 255, 50));
     }
 }
+
+/// The first of `range` `predicate` holds for, or its end. Once `predicate` holds it has to
+/// keep holding for the rest of `range`.
+fn partition_point(range: Range<usize>, predicate: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (range.start, range.end);
+    while low < high {
+        let middle = low + (high - low).div_euclid(2);
+        if predicate(middle) {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    low
+}
+
+/// `y.atan2(x)` to within about `1e-5` radians, several times faster.
+fn approximate_atan2(y: f32, x: f32) -> f32 {
+    let (x_abs, y_abs) = (x.abs(), y.abs());
+    let ratio = x_abs.min(y_abs) / x_abs.max(y_abs).max(f32::MIN_POSITIVE);
+    let squared = ratio * ratio;
+    let mut angle = (-0.046_496_475_f32)
+        .mul_add(squared, 0.159_314_22)
+        .mul_add(squared, -0.327_622_76)
+        * squared;
+    angle = angle.mul_add(ratio, ratio);
+    if y_abs > x_abs {
+        angle = std::f32::consts::FRAC_PI_2 - angle;
+    }
+    if x < 0.0 {
+        angle = std::f32::consts::PI - angle;
+    }
+    if y < 0.0 {
+        angle = -angle;
+    }
+    angle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::NoiseSettings;
+
+    /// A planet whose surface is a circle of `radius` around `center`, sampled at `resolution` points.
+    fn circle_planet(center: Vec2, radius: f32, resolution: u16) -> Planet {
+        let noise = Noise::new(1, NoiseSettings::default()).expect("default noise should be valid");
+        let surface = (0..resolution)
+            .map(|point| {
+                let angle = f32::from(point) * std::f32::consts::TAU / f32::from(resolution);
+                center + vec2(angle.sin(), angle.cos()) * radius
+            })
+            .collect();
+        Planet::from_surface(center, radius, surface, &noise)
+    }
+
+    #[test]
+    fn contains_points_inside_a_planet_away_from_the_origin() {
+        let center = vec2(1000.0, -500.0);
+        let planet = circle_planet(center, 100.0, 64);
+        assert!(Planet::contains_point(&planet, center + vec2(50.0, 0.0)));
+        assert!(Planet::contains_point(&planet, center + vec2(-30.0, -60.0)));
+        assert!(!Planet::contains_point(&planet, center + vec2(150.0, 0.0)));
+        assert!(!Planet::contains_point(&planet, center + vec2(0.0, -101.0)));
+        // Inside a planet at the origin, but not this one.
+        assert!(!Planet::contains_point(&planet, vec2(0.0, 0.0)));
+    }
+
+    #[test]
+    fn contains_the_center() {
+        let center = vec2(-300.0, 700.0);
+        let planet = circle_planet(center, 100.0, 64);
+        assert!(Planet::contains_point(&planet, center));
+    }
+
+    #[test]
+    fn contains_points_on_both_sides_of_the_seam() {
+        let center = vec2(1000.0, -500.0);
+        let planet = circle_planet(center, 100.0, 64);
+        // Angle 0 points along +y, just left of it the angle wraps around to almost TAU.
+        for side in [0.0, 0.01, -0.01] {
+            assert!(Planet::contains_point(&planet, center + vec2(side, 99.0)));
+            assert!(!Planet::contains_point(&planet, center + vec2(side, 101.0)));
+        }
+    }
+
+    #[test]
+    fn points_on_the_surface_are_outside() {
+        let center = vec2(1000.0, -500.0);
+        let planet = circle_planet(center, 100.0, 64);
+        let surface_point = planet.surface_point(0.0);
+        assert_eq!(surface_point, center + vec2(0.0, 100.0));
+        assert!(!Planet::contains_point(&planet, surface_point));
+        let direction = (surface_point - center).normalize();
+        assert!(Planet::contains_point(
+            &planet,
+            surface_point - direction * 0.01
+        ));
+    }
+
+    #[test]
+    fn sweeps_stop_at_the_surface() {
+        let center = vec2(1000.0, -500.0);
+        let planet = circle_planet(center, 100.0, 64);
+        let contact = planet
+            .sweep(center + vec2(0.0, 150.0), center + vec2(0.0, 50.0))
+            .expect("falling straight down should hit the ground");
+        assert!((contact.time - 0.5).abs() < 1e-4);
+        assert!((contact.point - (center + vec2(0.0, 100.0))).length() < 1e-3);
+        // Hitting a surface point, the normal is that of a segment on either side of it.
+        assert!(contact.normal.y > 0.99);
+        // Across the seam, where the angle wraps from TAU back to 0.
+        let contact = planet
+            .sweep(center + vec2(-10.0, 120.0), center + vec2(10.0, 80.0))
+            .expect("a move across the seam should hit the ground");
+        assert!((contact.point.distance(center) - 100.0).abs() < 0.2);
+        // Through the whole planet, the near side is hit first.
+        let contact = planet
+            .sweep(center + vec2(-150.0, 0.0), center + vec2(150.0, 0.0))
+            .expect("a move through the planet should hit the ground");
+        assert!((contact.point - (center + vec2(-100.0, 0.0))).length() < 0.2);
+        assert!(contact.normal.x < -0.99);
+    }
+
+    #[test]
+    fn sweeps_miss_when_staying_above_ground() {
+        let center = vec2(1000.0, -500.0);
+        let planet = circle_planet(center, 100.0, 64);
+        assert_eq!(
+            planet.sweep(center + vec2(-50.0, 120.0), center + vec2(50.0, 120.0)),
+            None
+        );
+        assert_eq!(
+            planet.sweep(center + vec2(0.0, 150.0), center + vec2(0.0, 101.0)),
+            None
+        );
+    }
+
+    /// Rasterizes the planet both ways, returning how many pixels differ.
+    fn rasterizer_mismatches(planet: &Planet) -> usize {
+        let Rect { x, y, w, h } = planet.image_extents();
+        let (width, height) = (
+            pixels(w).expect("the planet should fit an image"),
+            pixels(h).expect("the planet should fit an image"),
+        );
+        let fast = Planet::as_image_region(planet, vec2(x, y), width, height);
+        let reference = Planet::as_image_region_per_pixel(planet, vec2(x, y), width, height);
+        fast.bytes
+            .chunks_exact(4)
+            .zip(reference.bytes.chunks_exact(4))
+            .filter(|&(fast, reference)| fast != reference)
+            .count()
+    }
+
+    #[test]
+    fn rasterizers_agree() {
+        let noise = Noise::new(7, NoiseSettings::default()).expect("default noise should be valid");
+        let mut planet = Planet::new(vec2(-400.0, 250.0), 60.0, 256, &noise);
+        assert_eq!(rasterizer_mismatches(&planet), 0);
+        planet.sea_level = None;
+        assert_eq!(rasterizer_mismatches(&planet), 0);
+        planet.carve_caves(&noise);
+        assert_eq!(rasterizer_mismatches(&planet), 0);
+        planet.sea_level = Some(DEFAULT_SEA_LEVEL);
+        assert_eq!(rasterizer_mismatches(&planet), 0);
+    }
+
+    /// A planet shaped from `seed` the way the world shapes its first planet.
+    fn seeded_planet(seed: u64) -> Planet {
+        let noise = Noise::new(hash_seed(seed, 0), NoiseSettings::default())
+            .expect("default noise should be valid");
+        let mut planet = Planet::new(vec2(0.0, 0.0), 80.0, 360, &noise);
+        planet.carve_caves(&noise);
+        planet
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_planet() {
+        let (first, second) = (seeded_planet(1234), seeded_planet(1234));
+        let bits = |planet: &Planet| -> Vec<[u32; 2]> {
+            planet
+                .surface
+                .iter()
+                .map(|point| [point.x.to_bits(), point.y.to_bits()])
+                .collect()
+        };
+        assert_eq!(bits(&first), bits(&second));
+        let image = |planet: &Planet| {
+            Planet::as_image(planet)
+                .expect("the planet should fit an image")
+                .bytes
+        };
+        assert!(image(&first) == image(&second), "the images differ");
+        assert_ne!(bits(&first), bits(&seeded_planet(1235)));
+    }
+
+    #[test]
+    fn noise_settings_do_not_change_how_high_the_terrain_reaches() {
+        let settings = NoiseSettings {
+            octaves: 16,
+            amplitude: 40.0,
+            gain: 1.0,
+            range: (-250.0, 900.0),
+            ..NoiseSettings::default()
+        };
+        let noise = Noise::new(11, settings).expect("the settings should be valid");
+        let planet = Planet::new(vec2(0.0, 0.0), 100.0, 720, &noise);
+        for point in &planet.surface {
+            let height = point.length() - planet.radius();
+            assert!(
+                (-0.01..=100.0f32.mul_add(MAX_HEIGHT, 0.01)).contains(&height),
+                "{height}"
+            );
+        }
+    }
+}
@@ -1,21 +1,334 @@
+use crate::collision::Contact;
 use crate::common::*;
+use crate::planet::Planet;
 use macroquad::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Acceleration from walking input, in units per second squared.
+const WALK_ACCELERATION: f32 = 2400.0;
+/// How quickly the ground slows the player down, per second.
+const GROUND_FRICTION: f32 = 6.0;
+/// Fraction of the walking acceleration available while airborne.
+const AIR_CONTROL: f32 = 0.25;
+/// How far above the surface the player still counts as standing on it.
+const GROUND_TOLERANCE: f32 = 2.0;
+/// Steepest ground, in radians, the player can stand on inside caves.
+const MAX_WALKABLE_SLOPE: f32 = 1.0;
+/// Fastest the player can move away from the ground it stood on and still stay on it,
+/// on top of what running along curved ground adds. The kinks between surface segments
+/// lift it off a little.
+const GROUND_STICK_SPEED: f32 = 2.0;
+/// Gap kept between the player and the surface, so sweeps start outside of it.
+const COLLISION_SKIN: f32 = 0.5;
+/// How high a jump lifts the player, the same on every planet unless it would escape.
+const JUMP_HEIGHT: f32 = 120.0;
+/// Fastest jump as a fraction of escape speed, small planets hold on to the player.
+const MAX_JUMP_ESCAPE_FRACTION: f32 = 0.7;
+const JETPACK_ACCELERATION: f32 = 1200.0;
+/// Seconds of jetpack thrust in a full tank.
+pub const MAX_FUEL: f32 = 3.0;
+/// Seconds of thrust refilled per second spent on the ground.
+const FUEL_REFILL_RATE: f32 = 1.0;
+const ORBITAL_THRUST: f32 = 300.0;
+/// Fraction of the walking acceleration available while swimming.
+const SWIM_CONTROL: f32 = 0.5;
+/// How strongly water pushes up against gravity, above 1 the player floats.
+const BUOYANCY: f32 = 1.1;
+/// How quickly water slows the player down, per second.
+const WATER_DRAG: f32 = 2.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MovementMode {
+    /// Runs along the ground and jumps.
+    Walking,
+    /// Walks, and flies with thrust while there is fuel in the tank.
+    Jetpack,
+    /// Gentle unlimited thrust with no drag, coasting follows a Keplerian orbit.
+    Orbital,
+}
+
+/// Speed along the surface normal that lifts the player `JUMP_HEIGHT` off `planet` from
+/// `position`, as far as the gravity there tells, capped below escape speed.
+fn jump_speed(planet: &Planet, position: Vec2) -> f32 {
+    let gravity = planet.gravity_at(position).length();
+    let distance = position.distance(planet.center).max(planet.radius());
+    let escape = (2.0 * planet.gravitational_parameter() / distance).sqrt();
+    (2.0 * gravity * JUMP_HEIGHT)
+        .sqrt()
+        .min(MAX_JUMP_ESCAPE_FRACTION * escape)
+}
+
+impl MovementMode {
+    #[must_use]
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Walking => Self::Jetpack,
+            Self::Jetpack => Self::Orbital,
+            Self::Orbital => Self::Walking,
+        }
+    }
+}
 
 pub struct Player {
     pub pos: Vec2,
     pub rotation: f32,
+    pub velocity: Vec2,
+    pub grounded: bool,
+    pub mode: MovementMode,
+    pub fuel: f32,
+    /// Whether the player is in an ocean.
+    pub submerged: bool,
+    /// Where the player last touched the ground.
+    pub contact: Option<Contact>,
+    movement: Vec2,
+    jump_requested: bool,
 }
 
 impl Player {
     pub fn new(pos: Vec2) -> Self {
-        Self { pos, rotation: 0.0 }
+        Self {
+            pos,
+            rotation: 0.0,
+            velocity: Vec2::new(0.0, 0.0),
+            grounded: false,
+            mode: MovementMode::Walking,
+            fuel: MAX_FUEL,
+            submerged: false,
+            contact: None,
+            movement: Vec2::new(0.0, 0.0),
+            jump_requested: false,
+        }
+    }
+
+    /// Advances the player by `dt` seconds, pulled towards and standing on `planet`.
+    pub fn update(&mut self, dt: f32, planet: Option<&Planet>) {
+        let up = planet.map_or(self.pos, |planet| self.pos - planet.center);
+        self.rotation = -up.angle_between(Vec2::new(0.0, 1.0)) - std::f32::consts::PI;
+        let up = up.normalize_or_zero();
+        self.submerged = planet.is_some_and(|planet| planet.is_underwater(self.pos));
+
+        if std::mem::take(&mut self.jump_requested) && self.grounded {
+            let normal = self.contact.map_or(up, |contact| contact.normal);
+            let speed = planet.map_or(0.0, |planet| jump_speed(planet, self.pos));
+            self.velocity += normal * speed;
+            self.grounded = false;
+        }
+
+        let direction = rotate(self.movement, self.rotation);
+        // On the ground the jetpack only takes off, walking along the ground uses no fuel.
+        let thrusting =
+            direction.length_squared() > 0.0 && (!self.grounded || direction.dot(up) > 0.0);
+        let mut acceleration = match self.mode {
+            MovementMode::Jetpack if thrusting && self.fuel > 0.0 => {
+                self.fuel = (self.fuel - dt).max(0.0);
+                direction * JETPACK_ACCELERATION
+            }
+            MovementMode::Orbital if !self.grounded => direction * ORBITAL_THRUST,
+            MovementMode::Walking | MovementMode::Jetpack | MovementMode::Orbital => {
+                let walk = direction * WALK_ACCELERATION;
+                if self.grounded {
+                    // Only walk along the ground, pushing into it or off it does nothing.
+                    walk - up * walk.dot(up)
+                } else if self.submerged {
+                    walk * SWIM_CONTROL
+                } else if self.mode == MovementMode::Orbital {
+                    Vec2::new(0.0, 0.0)
+                } else {
+                    walk * AIR_CONTROL
+                }
+            }
+        };
+        if self.grounded {
+            let tangential = self.velocity - up * self.velocity.dot(up);
+            self.velocity -= tangential * (GROUND_FRICTION * dt).min(1.0);
+            self.fuel = FUEL_REFILL_RATE.mul_add(dt, self.fuel).min(MAX_FUEL);
+        }
+        if let Some(planet) = planet {
+            let gravity = planet.gravity_at(self.pos);
+            acceleration += gravity;
+            if self.submerged {
+                acceleration -= gravity * BUOYANCY;
+            }
+        }
+        // Semi-implicit Euler keeps coasting orbits from drifting apart.
+        self.velocity += acceleration * dt;
+        if self.submerged {
+            self.velocity -= self.velocity * (WATER_DRAG * dt).min(1.0);
+        }
+        let target = self.pos + self.velocity * dt;
+
+        // Anything moving off the ground slower than this is still falling onto it. Running
+        // along curved ground turns the velocity away from it by v²/r every second.
+        let lift_off_speed = match planet {
+            Some(planet) if self.grounded => {
+                let tangential = self.velocity - up * self.velocity.dot(up);
+                let distance = self.pos.distance(planet.center).max(1.0);
+                (tangential.length_squared() / distance).mul_add(dt, GROUND_STICK_SPEED)
+            }
+            Some(_) | None => 0.0,
+        };
+        self.grounded = false;
+        self.contact = None;
+        let Some(planet) = planet else {
+            self.pos = target;
+            return;
+        };
+        if let Some(contact) = planet.sweep(self.pos, target) {
+            self.pos = contact.push_out(COLLISION_SKIN);
+            self.velocity = contact.slide(self.velocity);
+            self.contact = Some(contact);
+            self.grounded = contact.slope(up) <= MAX_WALKABLE_SLOPE;
+        } else {
+            self.pos = target;
+        }
+        if planet.has_caves() {
+            // Caves have no single surface to snap to, feel for ground just below instead.
+            let falling = self.velocity.dot(up) <= lift_off_speed;
+            if !self.grounded && falling {
+                let probe = planet.sweep(self.pos, self.pos - up * GROUND_TOLERANCE);
+                if let Some(contact) =
+                    probe.filter(|contact| contact.slope(up) <= MAX_WALKABLE_SLOPE)
+                {
+                    self.contact = Some(contact);
+                    self.grounded = true;
+                }
+            }
+            return;
+        }
+
+        let altitude = planet.altitude(self.pos);
+        let falling = self.velocity.dot(up) <= lift_off_speed;
+        if altitude <= 0.0 || (altitude <= GROUND_TOLERANCE && falling) {
+            self.pos -= up * (altitude - COLLISION_SKIN);
+            let radial = self.velocity.dot(up);
+            if radial < lift_off_speed {
+                self.velocity -= up * radial;
+            }
+            self.grounded = true;
+            self.contact = Some(Contact {
+                point: self.pos - up * COLLISION_SKIN,
+                normal: planet.surface_normal(planet.angle_of(self.pos)),
+                time: 1.0,
+            });
+        }
+    }
+
+    /// Sets the walking or thrust direction in the player's own frame, `-y` is up.
+    pub const fn set_movement(&mut self, movement: Vec2) {
+        self.movement = movement;
+    }
+
+    /// Jumps off the surface on the next update, if the player is standing on it by then.
+    pub const fn jump(&mut self) {
+        self.jump_requested = true;
+    }
+
+    pub const fn set_mode(&mut self, mode: MovementMode) {
+        self.mode = mode;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::{Noise, NoiseSettings};
+    use macroquad::math::vec2;
+
+    const DT: f32 = 1.0 / 120.0;
+
+    /// A round, dry planet of `radius` at the origin.
+    fn planet(radius: f32) -> Planet {
+        let noise = Noise::new(1, NoiseSettings::default()).expect("default noise should be valid");
+        let surface = (0..720)
+            .map(|point| {
+                let angle = ring_angle(point, 720);
+                vec2(angle.sin(), angle.cos()) * radius
+            })
+            .collect();
+        let mut planet = Planet::from_surface(vec2(0.0, 0.0), radius, surface, &noise);
+        planet.sea_level = None;
+        planet
+    }
+
+    /// A player in `mode` standing on top of `planet`.
+    fn standing(planet: &Planet, mode: MovementMode) -> Player {
+        let mut player = Player::new(vec2(0.0, planet.radius() + 1.0));
+        player.set_mode(mode);
+        for _ in 0..10 {
+            player.update(DT, Some(planet));
+        }
+        assert!(player.grounded, "the player should land");
+        player
+    }
+
+    #[test]
+    fn walking_follows_the_ground() {
+        let planet = planet(400.0);
+        let mut player = standing(&planet, MovementMode::Walking);
+        player.set_movement(vec2(1.0, 0.0));
+        for _ in 0..120 {
+            player.update(DT, Some(&planet));
+        }
+        assert!(player.grounded);
+        assert!(player.pos.x.abs() > 10.0, "{}", player.pos);
+        assert!(planet.altitude(player.pos).abs() < GROUND_TOLERANCE);
+    }
+
+    #[test]
+    fn jumps_come_back_down_on_small_and_large_planets() {
+        for radius in [100.0, 150.0, 400.0, 1500.0] {
+            let planet = planet(radius);
+            let mut player = standing(&planet, MovementMode::Walking);
+            player.jump();
+            let mut highest = 0.0_f32;
+            let mut landed = false;
+            for _ in 0..120 * 20 {
+                player.update(DT, Some(&planet));
+                highest = highest.max(planet.altitude(player.pos));
+                if player.grounded {
+                    landed = true;
+                    break;
+                }
+            }
+            assert!(landed, "the player escaped a planet of radius {radius}");
+            assert!(highest > 10.0, "the jump barely left the ground");
+            assert!(highest < 3.0 * JUMP_HEIGHT, "{highest}");
+        }
     }
 
-    pub fn update(&mut self) {
-        self.rotation = -self.pos.angle_between(Vec2::new(0.0, 1.0)) - std::f32::consts::PI;
+    #[test]
+    fn the_jetpack_burns_fuel_only_in_the_air() {
+        let planet = planet(400.0);
+        let mut player = standing(&planet, MovementMode::Jetpack);
+        player.set_movement(vec2(1.0, 0.0));
+        for _ in 0..60 {
+            player.update(DT, Some(&planet));
+        }
+        assert!(
+            (player.fuel - MAX_FUEL).abs() < f32::EPSILON,
+            "{}",
+            player.fuel
+        );
+        player.set_movement(vec2(0.0, -1.0));
+        for _ in 0..60 {
+            player.update(DT, Some(&planet));
+        }
+        assert!(!player.grounded);
+        assert!(player.fuel < MAX_FUEL);
+        assert!(planet.altitude(player.pos) > 10.0);
     }
 
-    pub fn apply_speed(&mut self, speed: Vec2) {
-        self.pos += rotate(speed, self.rotation);
+    #[test]
+    fn orbits_coast_without_input() {
+        let planet = planet(400.0);
+        let distance = 800.0;
+        let mut player = Player::new(vec2(0.0, distance));
+        player.set_mode(MovementMode::Orbital);
+        player.velocity = vec2((planet.gravitational_parameter() / distance).sqrt(), 0.0);
+        for _ in 0..120 * 10 {
+            player.update(DT, Some(&planet));
+            let drift = (player.pos.length() - distance).abs();
+            assert!(drift < distance * 0.02, "{drift}");
+        }
     }
 }
@@ -0,0 +1,357 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::noise::{NoiseSettings, NoiseSettingsError};
+use crate::planet::MIN_RESOLUTION;
+use crate::player::MovementMode;
+
+/// Version new saves are written with, bumped whenever the format changes.
+/// Fields added later should default when missing, so older saves still parse,
+/// anything that can't be defaulted is upgraded in `migrate`.
+pub const SAVE_VERSION: u64 = 1;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    MissingVersion,
+    /// Written by a newer version of the game, or not a save at all.
+    UnsupportedVersion(u64),
+    UnknownPalette(String),
+    Noise(NoiseSettingsError),
+    /// The planet at this index doesn't describe a valid shape.
+    InvalidPlanet(usize),
+    /// The player's position or motion isn't a number.
+    InvalidPlayer,
+    /// The camera's view isn't a number, or has no size.
+    InvalidCamera,
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Io(ref error) => write!(f, "{error}"),
+            Self::Format(ref error) => write!(f, "malformed save: {error}"),
+            Self::MissingVersion => f.write_str("the save has no version"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "save version {version} isn't supported, this game reads up to version {SAVE_VERSION}"
+            ),
+            Self::UnknownPalette(ref name) => write!(f, "unknown palette {name}"),
+            Self::Noise(ref error) => write!(f, "invalid noise settings: {error}"),
+            Self::InvalidPlanet(index) => write!(f, "planet {index} has an invalid shape"),
+            Self::InvalidPlayer => f.write_str("the player's position or motion is invalid"),
+            Self::InvalidCamera => f.write_str("the camera's view is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Format(error)
+    }
+}
+
+impl From<NoiseSettingsError> for SaveError {
+    fn from(error: NoiseSettingsError) -> Self {
+        Self::Noise(error)
+    }
+}
+
+/// Everything needed to bring a world back, points are `[x, y]` pairs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldSave {
+    pub version: u64,
+    pub seed: u64,
+    /// The stream of the seed the next spawned planet is shaped by.
+    pub next_stream: u64,
+    /// Settings new planets are shaped with.
+    pub noise: NoiseSettings,
+    /// Name of the palette new planets are colored with.
+    pub palette: String,
+    pub planets: Vec<PlanetSave>,
+    pub player: Option<PlayerSave>,
+    pub camera: CameraSave,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlanetSave {
+    pub center: [f32; 2],
+    pub radius: f32,
+    pub noise_seed: u64,
+    /// The stream of the world seed `noise_seed` was derived from.
+    pub stream: u64,
+    pub noise: NoiseSettings,
+    pub palette: String,
+    pub sea_level: Option<f32>,
+    /// The surface points with any terrain edits, evenly spread by angle.
+    pub surface: Vec<[f32; 2]>,
+    pub caves: Option<CavesSave>,
+}
+
+/// A carved density field, with any terrain edits.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CavesSave {
+    pub origin: [f32; 2],
+    pub cell_size: f32,
+    pub columns: usize,
+    /// Density samples row by row.
+    pub values: Vec<f32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerSave {
+    pub position: [f32; 2],
+    pub rotation: f32,
+    pub velocity: [f32; 2],
+    pub mode: MovementMode,
+    pub fuel: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraSave {
+    pub target: [f32; 2],
+    pub rotation: f32,
+    pub zoom: [f32; 2],
+}
+
+impl WorldSave {
+    pub fn write(&self, path: &Path) -> Result<(), SaveError> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Reads a save written by this or any older version of the game.
+    pub fn read(path: &Path) -> Result<Self, SaveError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses a save written by this or any older version of the game.
+    pub fn from_json(json: &str) -> Result<Self, SaveError> {
+        let mut save: Value = serde_json::from_str(json)?;
+        let version = save
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or(SaveError::MissingVersion)?;
+        if version == 0 || version > SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion(version));
+        }
+        migrate(&mut save, version);
+        Ok(serde_json::from_value(save)?)
+    }
+
+    /// Checks the planets, the player and the camera, so a broken save is turned down
+    /// before any of it replaces the world.
+    pub fn validate(&self) -> Result<(), SaveError> {
+        for (index, planet) in self.planets.iter().enumerate() {
+            planet.validate(index)?;
+        }
+        if let Some(player) = self.player.as_ref() {
+            player.validate()?;
+        }
+        self.camera.validate()
+    }
+}
+
+impl PlanetSave {
+    /// Checks the shape describes a planet, `index` is its place in the world for errors.
+    /// The size of the caves' grid is checked when their density field is built from them.
+    pub fn validate(&self, index: usize) -> Result<(), SaveError> {
+        let valid = self.radius.is_finite()
+            && self.radius > 0.0
+            && self.center.iter().all(|value| value.is_finite())
+            && self.surface.len() >= MIN_RESOLUTION
+            && self.surface.iter().flatten().all(|value| value.is_finite())
+            && self.caves.as_ref().is_none_or(|caves| {
+                caves
+                    .origin
+                    .iter()
+                    .chain(&caves.values)
+                    .all(|value| value.is_finite())
+            });
+        if valid {
+            Ok(())
+        } else {
+            Err(SaveError::InvalidPlanet(index))
+        }
+    }
+}
+
+impl PlayerSave {
+    /// Checks the player is somewhere, and moving at some speed.
+    pub fn validate(&self) -> Result<(), SaveError> {
+        let valid = self
+            .position
+            .iter()
+            .chain(&self.velocity)
+            .chain([&self.rotation, &self.fuel])
+            .all(|value| value.is_finite());
+        if valid {
+            Ok(())
+        } else {
+            Err(SaveError::InvalidPlayer)
+        }
+    }
+}
+
+impl CameraSave {
+    /// Checks the camera looks at somewhere, through a view of some size.
+    pub fn validate(&self) -> Result<(), SaveError> {
+        let valid = self
+            .target
+            .iter()
+            .chain([&self.rotation])
+            .all(|value| value.is_finite())
+            && self.zoom.iter().all(|&zoom| zoom.is_finite() && zoom > 0.0);
+        if valid {
+            Ok(())
+        } else {
+            Err(SaveError::InvalidCamera)
+        }
+    }
+}
+
+/// Upgrades a save written with an older `version` of the format to the current one.
+fn migrate(save: &mut Value, version: u64) {
+    // Version 1 is the first format, upgrades from it go here one version at a time.
+    debug_assert!(version <= SAVE_VERSION);
+    if let Some(object) = save.as_object_mut() {
+        object.insert(String::from("version"), Value::from(SAVE_VERSION));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn planet_save(stream: u64) -> PlanetSave {
+        PlanetSave {
+            center: [100.0, -200.0],
+            radius: 50.0,
+            noise_seed: 9,
+            stream,
+            noise: NoiseSettings::default(),
+            palette: String::from("earth"),
+            sea_level: Some(0.25),
+            surface: vec![[100.0, -150.0], [143.3, -225.0], [56.7, -225.0]],
+            caves: Some(CavesSave {
+                origin: [40.0, -260.0],
+                cell_size: 60.0,
+                columns: 3,
+                values: vec![-1.0, 0.5, -1.0, 0.5, 1.0, 0.5, -1.0, 0.5, -1.0],
+            }),
+        }
+    }
+
+    fn world_save() -> WorldSave {
+        WorldSave {
+            version: SAVE_VERSION,
+            seed: 42,
+            next_stream: 2,
+            noise: NoiseSettings::default(),
+            palette: String::from("ice"),
+            planets: vec![planet_save(0), planet_save(1)],
+            player: Some(PlayerSave {
+                position: [100.0, -140.0],
+                rotation: 0.5,
+                velocity: [1.0, -2.0],
+                mode: MovementMode::Jetpack,
+                fuel: 0.75,
+            }),
+            camera: CameraSave {
+                target: [100.0, -140.0],
+                rotation: 0.5,
+                zoom: [0.01, 0.015],
+            },
+        }
+    }
+
+    #[test]
+    fn saves_round_trip() {
+        let save = world_save();
+        let json = serde_json::to_string(&save).expect("saves should serialize");
+        let read = WorldSave::from_json(&json).expect("saves should read back");
+        assert_eq!(read, save);
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let mut save = world_save();
+        save.version = SAVE_VERSION + 1;
+        let json = serde_json::to_string(&save).expect("saves should serialize");
+        assert!(matches!(
+            WorldSave::from_json(&json),
+            Err(SaveError::UnsupportedVersion(version)) if version == SAVE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn invalid_shapes_are_rejected() {
+        assert!(planet_save(0).validate(0).is_ok());
+        let mut short = planet_save(0);
+        short.surface.truncate(MIN_RESOLUTION - 1);
+        assert!(matches!(
+            short.validate(3),
+            Err(SaveError::InvalidPlanet(3))
+        ));
+        let mut not_finite = planet_save(0);
+        not_finite.surface.push([f32::NAN, 0.0]);
+        assert!(matches!(
+            not_finite.validate(1),
+            Err(SaveError::InvalidPlanet(1))
+        ));
+        let mut no_radius = planet_save(0);
+        no_radius.radius = 0.0;
+        assert!(no_radius.validate(0).is_err());
+        let mut broken_caves = planet_save(0);
+        let value = broken_caves
+            .caves
+            .as_mut()
+            .and_then(|caves| caves.values.get_mut(4));
+        if let Some(value) = value {
+            *value = f32::INFINITY;
+        }
+        assert!(broken_caves.validate(0).is_err());
+    }
+
+    #[test]
+    fn invalid_players_and_views_are_rejected() {
+        assert!(world_save().validate().is_ok());
+        let mut no_zoom = world_save();
+        no_zoom.camera.zoom = [0.01, 0.0];
+        assert!(matches!(no_zoom.validate(), Err(SaveError::InvalidCamera)));
+        let mut lost_camera = world_save();
+        lost_camera.camera.target = [f32::NAN, 0.0];
+        assert!(matches!(
+            lost_camera.validate(),
+            Err(SaveError::InvalidCamera)
+        ));
+        let mut flung_player = world_save();
+        if let Some(player) = flung_player.player.as_mut() {
+            player.velocity = [f32::INFINITY, 0.0];
+        }
+        assert!(matches!(
+            flung_player.validate(),
+            Err(SaveError::InvalidPlayer)
+        ));
+        let mut broken_planet = world_save();
+        if let Some(planet) = broken_planet.planets.get_mut(1) {
+            planet.radius = -5.0;
+        }
+        assert!(matches!(
+            broken_planet.validate(),
+            Err(SaveError::InvalidPlanet(1))
+        ));
+    }
+}
@@ -1,70 +1,670 @@
-use crate::camera::{top_down_camera_controls, Camera};
+use std::path::Path;
+
+use crate::camera::{mouse_camera_controls, screen_size, top_down_camera_controls, Camera};
 use macroquad::prelude::*;
-use once_cell::sync::OnceCell;
 
 use crate::common::*;
-use crate::noise::Noise;
-use crate::planet::Planet;
-use crate::player::Player;
+use crate::controls::{Action, InputMap};
+use crate::density::DensityField;
+use crate::job::{PlanetWorker, Progress};
+use crate::mesh::planet_mesh;
+use crate::noise::{Noise, NoiseSettings, NoiseSettingsError};
+use crate::palette::Palette;
+use crate::planet::{Planet, TerrainEdit};
+use crate::player::{Player, MAX_FUEL};
+use crate::save::{
+    CameraSave, CavesSave, PlanetSave, PlayerSave, SaveError, WorldSave, SAVE_VERSION,
+};
 
 pub const NOISE_SIZE: u16 = 2000;
+pub const SURFACE_RESOLUTION: usize = 2048;
+const CRATER_RADIUS: f32 = 80.0;
+/// How much one key press raises or lowers the sea level, in normalized altitude.
+const SEA_LEVEL_STEP: f32 = 0.05;
+/// Seconds of simulation in one step. Steps are the same length whatever the frame rate,
+/// so movement and physics play out the same on every machine.
+const TIME_STEP: f32 = 1.0 / 120.0;
+/// Most time simulated in one frame, after a hitch the game slows down instead of
+/// stalling on steps to catch up.
+const MAX_FRAME_TIME: f32 = 0.05;
+/// Most steps one frame takes, enough for `MAX_FRAME_TIME` and what carried over before it.
+const MAX_STEPS: u32 = 7;
+/// Where F5 saves the world to and F9 loads it from.
+const SAVE_PATH: &str = "world.json";
+/// Mixed into the world seed for the radii of spawned planets, which would otherwise come
+/// from the same hash as the noise seed of the planet they are spawned for.
+const SPAWN_RADIUS_SALT: u64 = 0x7370_6177_6e5f_7261;
+
+/// Centers and radii of the home planet and its moons.
+const SCENE: [([f32; 2], f32); 3] = [
+    ([0.0, 0.0], 1500.0),
+    ([7000.0, -2000.0], 450.0),
+    ([-5000.0, 4500.0], 150.0),
+];
+
+/// A planet in the world, with the noise it was shaped by and its rendered texture.
+pub struct WorldPlanet {
+    pub planet: Planet,
+    pub noise: Noise,
+    /// Which stream of the world seed the noise is seeded from, no two planets share one.
+    stream: u64,
+    pub texture: Option<Texture2D>,
+    /// Triangles the planet is drawn with until it has a texture, empty for caves.
+    mesh: Vec<Mesh>,
+    /// The area of the world the texture covers, edits may shrink `planet.extents` below it.
+    texture_extents: Rect,
+    /// Reshapes or renders in the background, `planet` and `texture` are swapped out when done.
+    worker: Option<PlanetWorker>,
+}
+
+impl WorldPlanet {
+    fn new(center: Vec2, radius: f32, noise: Noise, stream: u64, palette: Palette) -> Self {
+        let mut planet = Planet::new(center, radius, SURFACE_RESOLUTION, &noise);
+        planet.palette = palette;
+        Self {
+            mesh: planet_mesh(&planet),
+            planet,
+            noise,
+            stream,
+            texture: None,
+            texture_extents: Rect::new(0.0, 0.0, 0.0, 0.0),
+            worker: None,
+        }
+    }
+
+    /// Whether the planet is being reshaped or rendered in the background.
+    pub fn is_busy(&self) -> bool {
+        self.worker.as_ref().is_some_and(PlanetWorker::is_busy)
+    }
+
+    /// Runs `work` on the planet in the background, after any job already running.
+    fn start_job(&mut self, render: bool, work: impl FnOnce(Planet) -> Planet + Send + 'static) {
+        self.worker
+            .get_or_insert_with(PlanetWorker::spawn)
+            .start(&self.planet, render, work);
+    }
+
+    /// Swaps in the planet and texture of the background jobs once they are done.
+    fn finish_job(&mut self) {
+        self.collect_job(false);
+    }
+
+    /// Blocks until the background jobs are done and swaps in their planet and texture.
+    fn wait_for_job(&mut self) {
+        self.collect_job(true);
+    }
+
+    fn collect_job(&mut self, block: bool) {
+        let Some(worker) = self.worker.as_mut() else {
+            return;
+        };
+        let generated = match worker.finish(block) {
+            Progress::Idle | Progress::Running => return,
+            Progress::Done(generated) => *generated,
+            Progress::Failed => {
+                error!("planet generation failed, keeping the old planet");
+                self.worker = None;
+                return;
+            }
+        };
+        self.planet = generated.planet;
+        self.mesh = planet_mesh(&self.planet);
+        if let Some(image) = generated.image {
+            self.upload_texture(&image);
+        }
+    }
+
+    /// Reshapes the planet from its noise, keeping the center and radius.
+    fn rebuild(&mut self) {
+        let (seed, settings) = (self.noise.seed(), self.noise.settings());
+        self.start_job(self.texture.is_some(), move |old| {
+            let noise = Noise::new(seed, settings).expect("planet noise settings should be valid");
+            let mut planet = Planet::new(old.center, old.radius(), SURFACE_RESOLUTION, &noise);
+            if old.has_caves() {
+                planet.carve_caves(&noise);
+            }
+            planet.palette = old.palette;
+            planet.sea_level = old.sea_level;
+            planet
+        });
+    }
+
+    /// Switches between the radial surface and the cave representation.
+    pub fn toggle_caves(&mut self) {
+        let (seed, settings) = (self.noise.seed(), self.noise.settings());
+        self.start_job(self.texture.is_some(), move |mut planet| {
+            if planet.has_caves() {
+                planet.fill_caves();
+            } else {
+                let noise =
+                    Noise::new(seed, settings).expect("planet noise settings should be valid");
+                planet.carve_caves(&noise);
+            }
+            planet
+        });
+    }
+
+    /// Changes take effect right away, and are queued behind the background jobs so the
+    /// planet they swap in has them too.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.planet.palette = palette.clone();
+        self.mesh = planet_mesh(&self.planet);
+        if self.texture.is_some() || self.is_busy() {
+            self.start_job(self.texture.is_some(), move |mut planet| {
+                planet.palette = palette;
+                planet
+            });
+        }
+    }
+
+    /// Fills the oceans to `sea_level`, or drains them with `None`.
+    pub fn set_sea_level(&mut self, sea_level: Option<f32>) {
+        self.planet.sea_level = sea_level;
+        if self.texture.is_some() || self.is_busy() {
+            self.start_job(self.texture.is_some(), move |mut planet| {
+                planet.sea_level = sea_level;
+                planet
+            });
+        }
+    }
+
+    fn to_save(&self) -> PlanetSave {
+        let planet = &self.planet;
+        PlanetSave {
+            center: planet.center.into(),
+            radius: planet.radius(),
+            noise_seed: self.noise.seed(),
+            stream: self.stream,
+            noise: self.noise.settings(),
+            palette: String::from(planet.palette.name),
+            sea_level: planet.sea_level,
+            surface: planet.surface.iter().map(|&point| point.into()).collect(),
+            caves: planet.caves().map(|caves| CavesSave {
+                origin: caves.origin().into(),
+                cell_size: caves.cell_size(),
+                columns: caves.columns(),
+                values: caves.values().to_vec(),
+            }),
+        }
+    }
+
+    /// Brings back a saved planet, `index` is its place in the world for errors.
+    fn from_save(save: PlanetSave, index: usize) -> Result<Self, SaveError> {
+        let noise = Noise::new(save.noise_seed, save.noise)?;
+        save.validate(index)?;
+        let palette =
+            Palette::preset(&save.palette).ok_or(SaveError::UnknownPalette(save.palette))?;
+        let surface = save.surface.into_iter().map(Vec2::from).collect();
+        let mut planet = Planet::from_surface(save.center.into(), save.radius, surface, &noise);
+        if let Some(caves) = save.caves {
+            let field = DensityField::from_values(
+                caves.origin.into(),
+                caves.cell_size,
+                caves.columns,
+                caves.values,
+            )
+            .ok_or(SaveError::InvalidPlanet(index))?;
+            planet.set_caves(field);
+        }
+        planet.palette = palette;
+        planet.sea_level = save.sea_level;
+        Ok(Self {
+            mesh: planet_mesh(&planet),
+            planet,
+            noise,
+            stream: save.stream,
+            texture: None,
+            texture_extents: Rect::new(0.0, 0.0, 0.0, 0.0),
+            worker: None,
+        })
+    }
+
+    fn upload_texture(&mut self, image: &Image) {
+        self.delete_texture();
+        self.texture = Some(Texture2D::from_image(image));
+        self.texture_extents = self.planet.image_extents();
+    }
+
+    /// Edits the terrain, re-rendering only the changed part of the texture.
+    /// Planets being reshaped in the background get the edit after the reshaping too.
+    pub fn edit_terrain(&mut self, point: Vec2, radius: f32, edit: TerrainEdit) {
+        if self.is_busy() {
+            self.start_job(self.texture.is_some(), move |mut planet| {
+                planet.edit_terrain(point, radius, edit);
+                planet
+            });
+        }
+        let Some(dirty) = self.planet.edit_terrain(point, radius, edit) else {
+            return;
+        };
+        let Some(texture) = self.texture else {
+            self.mesh = planet_mesh(&self.planet);
+            return;
+        };
+        let Rect { x, y, w, h } = self.planet.image_extents();
+        let texture_extents = self.texture_extents;
+        let fits =
+            texture_extents.contains(vec2(x, y)) && texture_extents.contains(vec2(x + w, y + h));
+        if !fits {
+            self.start_job(true, |planet| planet);
+            return;
+        }
+        let Some(dirty) = dirty.intersect(texture_extents) else {
+            return;
+        };
+        let left = (dirty.left() - texture_extents.x).floor().max(0.0);
+        let top = (dirty.top() - texture_extents.y).floor().max(0.0);
+        let right = (dirty.right() - texture_extents.x)
+            .ceil()
+            .min(texture.width());
+        let bottom = (dirty.bottom() - texture_extents.y)
+            .ceil()
+            .min(texture.height());
+        let region = (
+            pixels(left),
+            pixels(top),
+            pixels(right - left),
+            pixels(bottom - top),
+        );
+        let (Some(left), Some(top), Some(width), Some(height)) = region else {
+            return;
+        };
+        let origin = texture_extents.point() + vec2(f32::from(left), f32::from(top));
+        let image = Planet::as_image_region(&self.planet, origin, width, height);
+        let gl = unsafe { get_internal_gl() };
+        texture.raw_miniquad_texture_handle().update_texture_part(
+            gl.quad_context,
+            i32::from(left),
+            i32::from(top),
+            i32::from(width),
+            i32::from(height),
+            &image.bytes,
+        );
+    }
+
+    /// Draws the texture, or the mesh until there is one. Planets with neither,
+    /// like caves that haven't been rendered yet, are drawn as outlines.
+    fn draw(&self) {
+        let planet = &self.planet;
+        if let Some(texture) = self.texture {
+            draw_texture(
+                texture,
+                self.texture_extents.x,
+                self.texture_extents.y,
+                color_u8!(255, 255, 255, 255),
+            );
+        } else if self.mesh.is_empty() {
+            planet.draw();
+        } else {
+            if let Some(sea_radius) = planet.sea_radius() {
+                draw_circle(
+                    planet.center.x,
+                    planet.center.y,
+                    sea_radius,
+                    planet.palette.water,
+                );
+            }
+            for chunk in &self.mesh {
+                draw_mesh(chunk);
+            }
+        }
+    }
+
+    fn delete_texture(&mut self) {
+        if let Some(texture) = self.texture.take() {
+            texture.delete();
+        }
+    }
+}
 
 pub struct World {
-    planet: OnceCell<Planet>,
-    planet_texture: Option<Texture2D>,
-    noise: Noise,
+    seed: u64,
+    seed_input: Option<String>,
+    planets: Vec<WorldPlanet>,
+    /// The stream of the world seed the next spawned planet is shaped by. It only ever
+    /// goes up, so a new planet never repeats the shape of one that is or was in the world.
+    next_stream: u64,
+    noise_settings: NoiseSettings,
+    palette: Palette,
+    show_noise: bool,
 
     main_camera: Camera,
     player: Option<Player>,
+    /// Where the player was and which way it faced before the last step, drawn
+    /// between that and where it is now.
+    previous_player: Option<(Vec2, f32)>,
+    /// Frame time not simulated yet, less than a `TIME_STEP`.
+    accumulator: f32,
+    controls: InputMap,
 }
 
 impl World {
     #[must_use]
-    pub fn new() -> Self {
+    pub fn new(seed: u64) -> Self {
         Self {
-            planet: OnceCell::new(),
-            planet_texture: None,
-            noise: Noise::new(),
+            seed,
+            seed_input: None,
+            planets: Vec::new(),
+            next_stream: 0,
+            noise_settings: NoiseSettings::default(),
+            palette: Palette::default(),
+            show_noise: false,
             main_camera: Camera::new(),
             player: None,
+            previous_player: None,
+            accumulator: 0.0,
+            controls: InputMap::default(),
         }
     }
 
     pub fn setup(&mut self) {
-        let noise = &self.noise;
-        let planet = self
-            .planet
-            .get_or_init(|| Planet::new(vec2(0.0, 0.0), 1500.0, noise));
+        for &([x, y], radius) in &SCENE {
+            self.spawn_planet(vec2(x, y), radius);
+        }
 
-        let player = Player::new(planet.surface[0]);
+        let home = self
+            .planets
+            .first()
+            .expect("the scene should have a planet");
+        let player = Player::new(home.planet.surface_point(0.0));
         self.player = Some(player);
-        //    let planet_image = Planet::as_image(planet);
-        //    self.planet_texture = Some(Texture2D::from_image(&planet_image));
+        self.previous_player = None;
+        self.follow_player();
+    }
+
+    fn planet_noise(&self, stream: u64) -> Noise {
+        Noise::new(hash_seed(self.seed, stream), self.noise_settings)
+            .expect("world noise settings should be valid")
+    }
+
+    /// Adds a planet shaped by the world seed, returns its index.
+    pub fn spawn_planet(&mut self, center: Vec2, radius: f32) -> usize {
+        let index = self.planets.len();
+        let stream = self.next_stream;
+        self.next_stream += 1;
+        let noise = self.planet_noise(stream);
+        let palette = self.palette.clone();
+        self.planets
+            .push(WorldPlanet::new(center, radius, noise, stream, palette));
+        index
+    }
+
+    pub fn remove_planet(&mut self, index: usize) -> Option<Planet> {
+        if index >= self.planets.len() {
+            return None;
+        }
+        let mut removed = self.planets.remove(index);
+        removed.delete_texture();
+        Some(removed.planet)
+    }
+
+    /// The planet whose surface is closest to `point`, with its index.
+    #[must_use]
+    pub fn nearest_planet(&self, point: Vec2) -> Option<(usize, &Planet)> {
+        nearest_planet(&self.planets, point)
+    }
+
+    fn nearest_world_planet_mut(&mut self, point: Vec2) -> Option<&mut WorldPlanet> {
+        let (index, _) = self.nearest_planet(point)?;
+        self.planets.get_mut(index)
+    }
+
+    /// Reshapes every planet from the new world `seed`.
+    pub fn regenerate(&mut self, seed: u64) {
+        self.seed = seed;
+        for world_planet in &mut self.planets {
+            world_planet
+                .noise
+                .set_seed(hash_seed(seed, world_planet.stream));
+            world_planet.rebuild();
+        }
+    }
+
+    pub fn set_noise_settings(
+        &mut self,
+        settings: NoiseSettings,
+    ) -> Result<(), NoiseSettingsError> {
+        settings.validate()?;
+        self.noise_settings = settings;
+        for world_planet in &mut self.planets {
+            world_planet.noise.set_settings(settings)?;
+            world_planet.rebuild();
+        }
+        Ok(())
+    }
+
+    fn cycle_noise_preset(&mut self) {
+        let presets = NoiseSettings::PRESETS;
+        let current = presets
+            .iter()
+            .position(|&(_, settings)| settings == self.noise_settings);
+        let next = current.map_or(0, |index| (index + 1) % presets.len());
+        if let Some(&(_, settings)) = presets.get(next) {
+            self.set_noise_settings(settings)
+                .expect("noise presets should be valid");
+        }
+    }
+
+    /// Recolors every planet with `palette`.
+    pub fn set_palette(&mut self, palette: Palette) {
+        for world_planet in &mut self.planets {
+            world_planet.set_palette(palette.clone());
+        }
+        self.palette = palette;
+    }
+
+    fn cycle_palette(&mut self) {
+        let presets = Palette::presets();
+        let current = presets
+            .iter()
+            .position(|palette| palette.name == self.palette.name);
+        let next = current.map_or(0, |index| (index + 1) % presets.len());
+        if let Some(palette) = presets.into_iter().nth(next) {
+            self.set_palette(palette);
+        }
+    }
+
+    /// Raises the sea level of the planet under the mouse by `change`, negative to lower it.
+    fn raise_sea_level(&mut self, change: f32) {
+        let mouse = self.main_camera.mouse_world_position();
+        if let Some(world_planet) = self.nearest_world_planet_mut(mouse) {
+            let sea_level = world_planet.planet.sea_level.unwrap_or(0.0) + change;
+            // Lowering the sea past the base radius drains it.
+            world_planet.set_sea_level((sea_level >= 0.0).then_some(sea_level));
+        }
+    }
+
+    /// Writes the world to `path`, after finishing any reshaping still in the background.
+    pub fn save(&mut self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        for world_planet in &mut self.planets {
+            world_planet.wait_for_job();
+        }
+        let camera = &self.main_camera;
+        let save = WorldSave {
+            version: SAVE_VERSION,
+            seed: self.seed,
+            next_stream: self.next_stream,
+            noise: self.noise_settings,
+            palette: String::from(self.palette.name),
+            planets: self.planets.iter().map(WorldPlanet::to_save).collect(),
+            player: self.player.as_ref().map(|player| PlayerSave {
+                position: player.pos.into(),
+                rotation: player.rotation,
+                velocity: player.velocity.into(),
+                mode: player.mode,
+                fuel: player.fuel,
+            }),
+            camera: CameraSave {
+                target: camera.target.into(),
+                rotation: camera.rotation,
+                zoom: camera.zoom.into(),
+            },
+        };
+        save.write(path.as_ref())
+    }
+
+    /// Replaces the world with the one saved at `path`, the world is left as it was on errors.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let save = WorldSave::read(path.as_ref())?;
+        save.validate()?;
+        save.noise.validate()?;
+        let palette =
+            Palette::preset(&save.palette).ok_or(SaveError::UnknownPalette(save.palette))?;
+        let planets = save
+            .planets
+            .into_iter()
+            .enumerate()
+            .map(|(index, planet)| WorldPlanet::from_save(planet, index))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for mut world_planet in std::mem::replace(&mut self.planets, planets) {
+            world_planet.delete_texture();
+        }
+        // Streams past every planet's, in case the save was edited by hand.
+        let streams = self
+            .planets
+            .iter()
+            .map(|world_planet| world_planet.stream.saturating_add(1));
+        self.next_stream = streams.fold(save.next_stream, u64::max);
+        self.seed = save.seed;
+        self.seed_input = None;
+        self.noise_settings = save.noise;
+        self.palette = palette;
+        self.player = save.player.map(|saved| {
+            let mut player = Player::new(saved.position.into());
+            player.rotation = saved.rotation;
+            player.velocity = saved.velocity.into();
+            player.mode = saved.mode;
+            player.fuel = saved.fuel.clamp(0.0, MAX_FUEL);
+            player
+        });
+        self.previous_player = None;
+        self.main_camera.target = save.camera.target.into();
+        self.main_camera.rotation = save.camera.rotation;
+        self.main_camera.zoom = save.camera.zoom.into();
+        self.main_camera.unfollow();
+        self.follow_player();
+        Ok(())
+    }
+
+    fn quick_save(&mut self) {
+        match self.save(SAVE_PATH) {
+            Ok(()) => info!("saved the world to {}", SAVE_PATH),
+            Err(error) => error!("couldn't save the world to {}: {}", SAVE_PATH, error),
+        }
+    }
+
+    fn quick_load(&mut self) {
+        match self.load(SAVE_PATH) {
+            Ok(()) => info!("loaded the world from {}", SAVE_PATH),
+            Err(error) => error!("couldn't load the world from {}: {}", SAVE_PATH, error),
+        }
+    }
+
+    pub fn set_controls(&mut self, controls: InputMap) {
+        self.controls = controls;
+    }
+
+    fn cycle_layout(&mut self) {
+        let presets = InputMap::presets();
+        let current = presets
+            .iter()
+            .position(|controls| controls.layout == self.controls.layout);
+        let next = current.map_or(0, |index| (index + 1) % presets.len());
+        if let Some(controls) = presets.into_iter().nth(next) {
+            self.controls = controls;
+        }
+    }
+
+    pub fn copy_seed_to_clipboard(&self) {
+        let seed = self.seed.to_string();
+        let gl = unsafe { get_internal_gl() };
+        miniquad::clipboard::set(gl.quad_context, &seed);
+    }
+
+    /// Handles typing a seed in, returns `true` while the seed prompt is open.
+    fn seed_input(&mut self) -> bool {
+        if self.controls.is_pressed(Action::CopySeed) {
+            self.copy_seed_to_clipboard();
+        }
+        if self.controls.is_pressed(Action::EnterSeed) && self.seed_input.is_none() {
+            self.seed_input = Some(String::new());
+            // Drop the characters typed before the prompt was opened.
+            while get_char_pressed().is_some() {}
+            return true;
+        }
+        let Some(typed) = self.seed_input.as_mut() else {
+            return false;
+        };
+        while let Some(character) = get_char_pressed() {
+            if character.is_ascii_digit() {
+                typed.push(character);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            typed.pop();
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            self.seed_input = None;
+        } else if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter) {
+            let seed = typed.parse::<u64>();
+            self.seed_input = None;
+            if let Ok(seed) = seed {
+                self.regenerate(seed);
+            }
+        }
+        true
     }
 
     pub fn input(&mut self) {
-        let lmb = is_mouse_button_pressed(MouseButton::Left);
-        let w = is_key_down(KeyCode::W) || is_key_down(KeyCode::Comma);
-        let s = is_key_down(KeyCode::S) || is_key_down(KeyCode::O);
-        let a = is_key_down(KeyCode::A);
-        let d = is_key_down(KeyCode::D) || is_key_down(KeyCode::E);
-
-        if let Some(player) = &mut self.player {
-            let mut player_move = Vec2::new(0.0, 0.0);
-            if w {
-                player_move.y -= 1.0;
-            } else if s {
-                player_move.y += 1.0;
+        if self.seed_input() {
+            return;
+        }
+        if self.controls.is_pressed(Action::NextNoisePreset) {
+            self.cycle_noise_preset();
+        }
+        if self.controls.is_pressed(Action::ShowNoise) {
+            self.show_noise = !self.show_noise;
+        }
+        if self.controls.is_pressed(Action::QuickSave) {
+            self.quick_save();
+        }
+        if self.controls.is_pressed(Action::QuickLoad) {
+            self.quick_load();
+        }
+        if self.controls.is_pressed(Action::NextLayout) {
+            self.cycle_layout();
+        }
+        if self.controls.is_pressed(Action::NextPalette) {
+            self.cycle_palette();
+        }
+        if self.controls.is_pressed(Action::SpawnPlanet) {
+            let mouse = self.main_camera.mouse_world_position();
+            let radius_seed = hash_seed(self.seed, SPAWN_RADIUS_SALT);
+            let radius = seeded_range(radius_seed, self.next_stream, 100.0, 400.0);
+            self.spawn_planet(mouse, radius);
+        }
+        if self.controls.is_pressed(Action::ToggleCaves) {
+            let mouse = self.main_camera.mouse_world_position();
+            if let Some(world_planet) = self.nearest_world_planet_mut(mouse) {
+                world_planet.toggle_caves();
             }
-            if a {
-                player_move.x -= 1.0;
-            } else if d {
-                player_move.x += 1.0;
+        }
+        if self.controls.is_pressed(Action::RaiseSeaLevel) {
+            self.raise_sea_level(SEA_LEVEL_STEP);
+        }
+        if self.controls.is_pressed(Action::LowerSeaLevel) {
+            self.raise_sea_level(-SEA_LEVEL_STEP);
+        }
+        if self.controls.is_pressed(Action::RemovePlanet) {
+            let mouse = self.main_camera.mouse_world_position();
+            if let Some((index, _)) = self.nearest_planet(mouse) {
+                self.remove_planet(index);
             }
-            player.apply_speed(player_move);
         }
+        self.player_input();
 
-        if lmb {
+        if self.controls.is_pressed(Action::Dig) {
             let camera = self.main_camera;
             debug!(
                 "{}",
@@ -78,44 +678,127 @@ impl World {
             let mouse = camera.mouse_world_position();
             debug!("mouse: {:?}, mouse_world: {}", mouse_position(), mouse);
 
-            if let Some(planet) = self.planet.get_mut() {
-                let new_planet = Planet::new(vec2(0.0, 0.0), 1500.0, &self.noise);
-                let planet_image = Planet::as_image(&new_planet);
-                self.planet_texture = Some(Texture2D::from_image(&planet_image));
-                std::mem::replace(planet, new_planet);
+            let edit = if self.controls.is_down(Action::Build) {
+                TerrainEdit::Build
+            } else {
+                TerrainEdit::Dig
+            };
+            if let Some(world_planet) = self.nearest_world_planet_mut(mouse) {
+                world_planet.edit_terrain(mouse, CRATER_RADIUS, edit);
+            }
+        }
+        if self.controls.is_pressed(Action::Regenerate) {
+            self.regenerate(::rand::random::<u64>());
+        }
+
+        self.camera_input();
+    }
+
+    fn player_input(&mut self) {
+        let controls = &self.controls;
+        let Some(player) = self.player.as_mut() else {
+            return;
+        };
+        let mut player_move = Vec2::new(0.0, 0.0);
+        if controls.is_down(Action::MoveUp) {
+            player_move.y -= 1.0;
+        } else if controls.is_down(Action::MoveDown) {
+            player_move.y += 1.0;
+        }
+        if controls.is_down(Action::MoveLeft) {
+            player_move.x -= 1.0;
+        } else if controls.is_down(Action::MoveRight) {
+            player_move.x += 1.0;
+        }
+        player.set_movement(player_move);
+        if controls.is_pressed(Action::Jump) {
+            player.jump();
+        }
+        if controls.is_pressed(Action::NextMovementMode) {
+            player.set_mode(player.mode.next());
+        }
+    }
+
+    /// Panning moves the camera freely, until it is told to follow the player again.
+    fn camera_input(&mut self) {
+        let dt = get_frame_time().min(MAX_FRAME_TIME);
+        top_down_camera_controls(&mut self.main_camera, &self.controls, dt);
+        mouse_camera_controls(&mut self.main_camera, &self.controls);
+        if self.controls.is_pressed(Action::FollowPlayer) {
+            self.follow_player();
+        }
+        if self.controls.is_pressed(Action::FitPlanet) {
+            let mouse = self.main_camera.mouse_world_position();
+            if let Some((_, planet)) = self.nearest_planet(mouse) {
+                let extents = planet.extents;
+                self.main_camera.fit(extents);
             }
         }
+    }
 
-        if is_key_down(KeyCode::LeftControl) {
-            top_down_camera_controls(&mut self.main_camera);
-        } else if let Some(player) = &self.player {
-            self.main_camera.target.x = player.pos.x;
-            self.main_camera.target.y = player.pos.y;
-            self.main_camera.rotation = player.rotation;
+    /// Points the camera's follow at where the player is drawn.
+    fn follow_player(&mut self) {
+        if let (Some((position, rotation)), Some(player)) =
+            (self.player_pose(), self.player.as_ref())
+        {
+            self.main_camera
+                .set_follow(Some(position), Some(rotation), player.velocity);
         }
     }
 
+    /// Where the player is drawn and which way it faces, between its last two steps
+    /// by how far the frame time has gotten into the next one.
+    fn player_pose(&self) -> Option<(Vec2, f32)> {
+        let player = self.player.as_ref()?;
+        let Some((position, rotation)) = self.previous_player else {
+            return Some((player.pos, player.rotation));
+        };
+        let alpha = self.accumulator / TIME_STEP;
+        Some((
+            position.lerp(player.pos, alpha),
+            angle_difference(rotation, player.rotation).mul_add(alpha, rotation),
+        ))
+    }
+
+    /// Simulates the time since the last frame in fixed steps, the remainder carries
+    /// over to the next frame. The camera eases along once per frame.
     pub fn update(&mut self) {
-        self.main_camera.update();
-        if let Some(player) = &mut self.player {
-            player.update();
+        for world_planet in &mut self.planets {
+            world_planet.finish_job();
+        }
+        let frame_time = get_frame_time().min(MAX_FRAME_TIME);
+        self.accumulator += frame_time;
+        for _ in 0..MAX_STEPS {
+            if self.accumulator < TIME_STEP {
+                break;
+            }
+            self.accumulator -= TIME_STEP;
+            self.step(TIME_STEP);
+        }
+        if self.main_camera.followed_pos.is_some() {
+            self.follow_player();
+        }
+        self.main_camera.update(frame_time);
+    }
+
+    /// Advances the simulation by `dt` seconds.
+    fn step(&mut self, dt: f32) {
+        if let Some(player) = self.player.as_mut() {
+            self.previous_player = Some((player.pos, player.rotation));
+            let planet = nearest_planet(&self.planets, player.pos).map(|(_, planet)| planet);
+            player.update(dt, planet);
         }
     }
 
     pub fn draw(&self) {
         clear_background(color_u8!(0, 0, 0, 255));
-        let zoom = vec2(self.main_camera.zoom.x, -self.main_camera.zoom.y);
-        set_camera(&Camera2D {
-            target: self.main_camera.target,
-            rotation: -self.main_camera.rotation.to_degrees(),
-            zoom,
-            ..Camera2D::default()
-        });
-
-        let planet = unsafe { self.planet.get_unchecked() };
+        set_camera(&self.main_camera.camera_2d());
 
         let mouse = self.main_camera.mouse_world_position();
-        let is_inside_planet = Planet::is_inside_expensive(planet, mouse);
+        let is_inside_planet = self
+            .planets
+            .iter()
+            .any(|world_planet| Planet::contains_point(&world_planet.planet, mouse));
         if is_inside_planet {
             draw_circle(mouse.x, mouse.y, 10.0, color_u8!(0, 255, 0, 255));
         } else {
@@ -128,34 +811,151 @@ impl World {
         let _top_left_x = center_x - width;
         let _top_left_y = center_y - height;
 
-        if let Some(player) = &self.player {
+        if let Some((position, _)) = self.player_pose() {
             draw_rectangle(
-                player.pos.x - 5.0,
-                player.pos.y - 5.0,
+                position.x - 5.0,
+                position.y - 5.0,
                 10.0,
                 10.0,
                 color_u8!(255, 255, 255, 255),
             );
-            draw_vector(
-                player.pos,
-                (self.planet.get().expect("should have a planet").center - player.pos).normalize()
-                    * 100.0, // - player.pos.normalize(),
-            );
+            if let Some((_, planet)) = self.nearest_planet(position) {
+                draw_vector(position, (planet.center - position).normalize() * 100.0);
+            }
         }
         draw_vector(Vec2::new(0.0, 0.0), Vec2::new(0.0, 1500.0));
-        let planet_x = planet.extents.x;
-        let planet_y = planet.extents.y;
 
-        if let Some(planet_texture) = self.planet_texture {
-            draw_texture(
-                planet_texture,
-                planet_x,
-                planet_y,
-                color_u8!(255, 255, 255, 255),
-            );
+        for world_planet in &self.planets {
+            world_planet.draw();
+        }
+        if self.show_noise {
+            if let Some(home) = self.planets.first() {
+                let half_size = f32::from(NOISE_SIZE) / 2.0;
+                home.noise.draw_at(-half_size, -half_size);
+            }
+        }
+
+        self.draw_hud();
+    }
+
+    /// The seed, noise, palette and planets, with the keys that change them.
+    fn draw_controls_hud(&self, color: Color) {
+        let key = |action| self.controls.hint(action);
+        draw_text(
+            &format!(
+                "seed: {} ({} copy, {} enter, {} random, {} save, {} load), {} to dig, {}+{} to build, {} drag to pan, {} follow, {} fit planet, layout: {} ({} next)",
+                self.seed,
+                key(Action::CopySeed),
+                key(Action::EnterSeed),
+                key(Action::Regenerate),
+                key(Action::QuickSave),
+                key(Action::QuickLoad),
+                key(Action::Dig),
+                key(Action::Build),
+                key(Action::Dig),
+                key(Action::DragPan),
+                key(Action::FollowPlayer),
+                key(Action::FitPlanet),
+                self.controls.layout,
+                key(Action::NextLayout),
+            ),
+            10.0,
+            20.0,
+            20.0,
+            color,
+        );
+        let preset = self.noise_settings.preset_name().unwrap_or("custom");
+        let busy = self
+            .planets
+            .iter()
+            .filter(|world_planet| world_planet.is_busy())
+            .count();
+        let planets = if busy > 0 {
+            format!("{} ({busy} reshaping)", self.planets.len())
         } else {
-            let planet = self.planet.get().expect("Planet should exist on draw");
-            planet.draw();
+            self.planets.len().to_string()
+        };
+        draw_text(
+            &format!(
+                "noise: {preset} ({} next preset, {} show), palette: {} ({} next), planets: {planets} ({} spawn, {} remove, {} caves, {} {} sea level)",
+                key(Action::NextNoisePreset),
+                key(Action::ShowNoise),
+                self.palette.name,
+                key(Action::NextPalette),
+                key(Action::SpawnPlanet),
+                key(Action::RemovePlanet),
+                key(Action::ToggleCaves),
+                key(Action::LowerSeaLevel),
+                key(Action::RaiseSeaLevel),
+            ),
+            10.0,
+            40.0,
+            20.0,
+            color,
+        );
+    }
+
+    fn draw_hud(&self) {
+        set_default_camera();
+        let color = color_u8!(255, 255, 255, 255);
+        self.draw_controls_hud(color);
+        let key = |action| self.controls.hint(action);
+        if let Some(player) = self.player.as_ref() {
+            let up = self
+                .nearest_planet(player.pos)
+                .map_or(player.pos, |(_, planet)| player.pos - planet.center);
+            let slope = player
+                .contact
+                .map_or(0.0, |contact| contact.slope(up).to_degrees());
+            draw_text(
+                &format!(
+                    "mode: {:?} ({} next, {} jump), fuel: {:.1}/{MAX_FUEL}, slope: {slope:.0}°{}",
+                    player.mode,
+                    key(Action::NextMovementMode),
+                    key(Action::Jump),
+                    player.fuel,
+                    if player.submerged { ", swimming" } else { "" }
+                ),
+                10.0,
+                60.0,
+                20.0,
+                color,
+            );
+        }
+        for world_planet in self
+            .planets
+            .iter()
+            .filter(|world_planet| world_planet.is_busy())
+        {
+            let center = self
+                .main_camera
+                .world_to_screen(world_planet.planet.center, screen_size());
+            draw_text("reshaping...", center.x, center.y, 20.0, color);
+        }
+        let mouse = self.main_camera.mouse_world_position();
+        let stratum = self
+            .nearest_planet(mouse)
+            .filter(|&(_, planet)| Planet::contains_point(planet, mouse))
+            .and_then(|(_, planet)| planet.stratum_at(mouse));
+        if let Some(stratum) = stratum {
+            draw_text(
+                &format!("digging into: {}", stratum.name),
+                10.0,
+                80.0,
+                20.0,
+                color,
+            );
+        }
+        if let Some(typed) = self.seed_input.as_ref() {
+            draw_text(&format!("new seed: {typed}_"), 10.0, 100.0, 20.0, color);
         }
     }
 }
+
+fn nearest_planet(planets: &[WorldPlanet], point: Vec2) -> Option<(usize, &Planet)> {
+    planets
+        .iter()
+        .map(|world_planet| &world_planet.planet)
+        .enumerate()
+        .min_by(|&(_, a), &(_, b)| a.altitude(point).total_cmp(&b.altitude(point)))
+}